@@ -21,7 +21,7 @@ use notecalc_lib::helper::*;
 use notecalc_lib::units::units::Units;
 use notecalc_lib::{
     Layer, NoteCalcApp, OutputMessage, OutputMessageCommandId, RenderAsciiTextMsg, RenderBuckets,
-    RenderStringMsg, RenderUtf8TextMsg, Variable, MAX_LINE_COUNT,
+    RenderStringMsg, RenderUtf8TextMsg, Variable, MAX_LINE_COUNT, VARIABLE_COUNT,
 };
 
 mod utils;
@@ -91,9 +91,7 @@ impl AppPointers {
 
     fn mut_vars<'a>(ptr: u32) -> &'a mut [Option<Variable>] {
         let ptr_holder = unsafe { &*(ptr as *const AppPointers) };
-        unsafe {
-            &mut (&mut *(ptr_holder.vars_ptr as *mut [Option<Variable>; MAX_LINE_COUNT + 1]))[..]
-        }
+        unsafe { &mut (&mut *(ptr_holder.vars_ptr as *mut [Option<Variable>; VARIABLE_COUNT]))[..] }
     }
 
     fn allocator<'a>(ptr: u32) -> &'a Bump {
@@ -239,6 +237,12 @@ pub fn handle_time(app_ptr: u32, now: u32) -> bool {
     return rerender_needed;
 }
 
+/// local time in seconds since 1970-01-01, used by `today` and `now`
+#[wasm_bindgen]
+pub fn set_current_time(local_secs: f64) {
+    notecalc_lib::datetime::set_current_time(local_secs as i64);
+}
+
 #[wasm_bindgen]
 pub fn handle_drag(app_ptr: u32, x: usize, y: usize) -> bool {
     return AppPointers::mut_app(app_ptr).handle_drag(
@@ -349,6 +353,13 @@ pub fn reparse_everything(app_ptr: u32) {
     );
 }
 
+/// Results are shown in their composite unit (e.g. kg m / s^2) instead of the named one (N)
+#[wasm_bindgen]
+pub fn set_keep_raw_units(app_ptr: u32, keep: bool) {
+    AppPointers::units(app_ptr).set_keep_raw_units(keep);
+    reparse_everything(app_ptr);
+}
+
 #[wasm_bindgen]
 pub fn render(app_ptr: u32) {
     send_render_commands_to_js(AppPointers::mut_render_bucket(app_ptr));
@@ -1,12 +1,19 @@
-use std::ops::BitXor;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
 use std::ops::Neg;
-use std::ops::Not;
+use std::str::FromStr;
 
+use crate::datetime::DateTime;
 use crate::matrix::MatrixData;
+use crate::rational::Rational;
 use crate::token_parser::{OperatorTokenType, Token, TokenType};
-use crate::units::consts::EMPTY_UNIT_DIMENSIONS;
-use crate::units::units::UnitOutput;
+use crate::units::consts::{
+    UnitDimensionExponent, BASE_UNIT_DIMENSION_COUNT, BASE_UNIT_DIMENSION_NAMES,
+    EMPTY_UNIT_DIMENSIONS,
+};
+use crate::units::units::{UnitOutput, Units};
 use crate::Variables;
+use num_bigint::BigInt;
 use rust_decimal::prelude::*;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -14,6 +21,36 @@ pub struct CalcResult {
     pub typ: CalcResultType,
     index_into_tokens: usize,
     index2_into_tokens: Option<usize>,
+    /// set by "to base N", the result is rendered in this radix
+    radix: Option<u8>,
+    /// set by e.g. "as u8", the bitwise operations keep this width
+    int_type: Option<IntType>,
+    /// set by an explicit unit conversion, the unit is rendered without simplification
+    unit_converted: bool,
+}
+
+/// Fixed-width integer with two's complement semantics, e.g. "as i16"
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IntType {
+    pub bits: u8,
+    pub signed: bool,
+}
+
+impl IntType {
+    /// The bit pattern of the value truncated to this width, e.g. -1 as i8 is 0xFF
+    pub fn to_unsigned(&self, num: &BigInt) -> BigInt {
+        let modulus = BigInt::from(1) << usize::from(self.bits);
+        ((num % &modulus) + &modulus) % &modulus
+    }
+
+    pub fn wrap(&self, num: &BigInt) -> BigInt {
+        let unsigned = self.to_unsigned(num);
+        if self.signed && unsigned.bits() == u64::from(self.bits) {
+            unsigned - (BigInt::from(1) << usize::from(self.bits))
+        } else {
+            unsigned
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -23,6 +60,14 @@ pub enum CalcResultType {
     Unit(UnitOutput),
     Quantity(Decimal, UnitOutput),
     Matrix(MatrixData),
+    Boolean(bool),
+    /// integers which don't fit into a Decimal, e.g. 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF
+    BigInt(BigInt),
+    /// real and imaginary parts in base unit, the unit is empty for plain complex numbers
+    Complex(Decimal, Decimal, UnitOutput),
+    /// exact result of a division which is not representable as a Decimal, e.g. 1/3
+    Rational(Rational),
+    Date(DateTime),
 }
 
 impl CalcResult {
@@ -31,6 +76,9 @@ impl CalcResult {
             typ,
             index_into_tokens: index,
             index2_into_tokens: None,
+            radix: None,
+            int_type: None,
+            unit_converted: false,
         }
     }
 
@@ -39,6 +87,9 @@ impl CalcResult {
             typ,
             index_into_tokens: index,
             index2_into_tokens: Some(index2),
+            radix: None,
+            int_type: None,
+            unit_converted: false,
         }
     }
 
@@ -46,6 +97,18 @@ impl CalcResult {
         self.index_into_tokens
     }
 
+    pub fn is_unit_converted(&self) -> bool {
+        self.unit_converted
+    }
+
+    pub fn get_radix(&self) -> Option<u8> {
+        self.radix
+    }
+
+    pub fn get_int_type(&self) -> Option<IntType> {
+        self.int_type
+    }
+
     pub fn set_token_error_flag<'text_ptr>(&self, tokens: &mut [Token<'text_ptr>]) {
         // TODO I could not reproduce it but it happened runtime, so I use 'get_mut'
         // later when those indices will be used correctly (now they are just dummy values lot of times),
@@ -66,6 +129,9 @@ impl CalcResult {
             }),
             index_into_tokens: 0,
             index2_into_tokens: None,
+            radix: None,
+            int_type: None,
+            unit_converted: false,
         }
     }
 
@@ -81,6 +147,90 @@ pub struct EvaluationResult {
     pub result: CalcResult,
 }
 
+/// Why a line could not be evaluated, it is shown when the cursor is on the line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CalcError {
+    DivisionByZero,
+    /// the dimensions of the two operands, e.g. "1 m + 1 s"
+    IncompatibleUnits(
+        [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+        [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+    ),
+    /// different currencies without a declared exchange rate
+    MissingExchangeRate,
+    Overflow,
+    WrongArgumentCount {
+        min: usize,
+        max: usize,
+        got: usize,
+    },
+    /// the referenced variable or line has no value, e.g. it has an error itself
+    UnknownVariable,
+    EmptyLineRange,
+    InvalidNumber,
+    InvalidUnitDefinition,
+    /// e.g. "unit m = 5", it would hide the builtin unit
+    BuiltinUnitName,
+    ZeroUnitValue,
+    InvalidOperation,
+}
+
+impl Display for CalcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn dimensions_to_string(
+            dimensions: &[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+        ) -> String {
+            let parts: Vec<String> = dimensions
+                .iter()
+                .zip(BASE_UNIT_DIMENSION_NAMES.iter())
+                .filter(|(exp, _name)| **exp != 0)
+                .map(|(exp, name)| {
+                    if *exp == 1 {
+                        name.to_string()
+                    } else {
+                        format!("{}^{}", name, exp)
+                    }
+                })
+                .collect();
+            if parts.is_empty() {
+                "no unit".to_owned()
+            } else {
+                parts.join(" * ")
+            }
+        }
+
+        match self {
+            CalcError::DivisionByZero => write!(f, "division by zero"),
+            CalcError::IncompatibleUnits(lhs, rhs) => write!(
+                f,
+                "incompatible units: {} and {}",
+                dimensions_to_string(lhs),
+                dimensions_to_string(rhs)
+            ),
+            CalcError::MissingExchangeRate => write!(f, "no exchange rate between the currencies"),
+            CalcError::Overflow => write!(f, "the result is too large"),
+            CalcError::WrongArgumentCount { min, max, got } => {
+                if min == max {
+                    write!(f, "expected {} argument(s), got {}", min, got)
+                } else if *max == usize::MAX {
+                    write!(f, "expected at least {} argument(s), got {}", min, got)
+                } else {
+                    write!(f, "expected {} to {} arguments, got {}", min, max, got)
+                }
+            }
+            CalcError::UnknownVariable => write!(f, "the referenced value has no result"),
+            CalcError::EmptyLineRange => write!(f, "there are no results in the range"),
+            CalcError::InvalidNumber => write!(f, "invalid number"),
+            CalcError::InvalidUnitDefinition => {
+                write!(f, "a unit must be defined by a number or a quantity")
+            }
+            CalcError::BuiltinUnitName => write!(f, "a builtin unit has the same name"),
+            CalcError::ZeroUnitValue => write!(f, "a unit can't be zero"),
+            CalcError::InvalidOperation => write!(f, "invalid operation"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ShuntingYardResult {
     pub typ: TokenType,
@@ -100,7 +250,8 @@ pub fn evaluate_tokens<'text_ptr>(
     tokens: &mut [Token<'text_ptr>],
     shunting_tokens: &mut Vec<ShuntingYardResult>,
     variables: &Variables,
-) -> Result<Option<EvaluationResult>, ()> {
+    units: &Units,
+) -> Result<Option<EvaluationResult>, CalcError> {
     let mut stack: Vec<CalcResult> = vec![];
     let mut there_was_unit_conversion = false;
     let mut assignment = false;
@@ -112,8 +263,24 @@ pub fn evaluate_tokens<'text_ptr>(
                 CalcResultType::Number(num.clone()),
                 token.index_into_tokens,
             )),
+            TokenType::BigIntLiteral(num) => stack.push(CalcResult::new(
+                CalcResultType::BigInt(num.clone()),
+                token.index_into_tokens,
+            )),
+            TokenType::ImaginaryLiteral(num) => stack.push(CalcResult::new(
+                complex_result(Decimal::zero(), num.clone(), UnitOutput::new()),
+                token.index_into_tokens,
+            )),
+            TokenType::DurationLiteral(secs, unit) => stack.push(CalcResult::new(
+                CalcResultType::Quantity(*secs, unit.clone()),
+                token.index_into_tokens,
+            )),
+            TokenType::DateLiteral(date) => stack.push(CalcResult::new(
+                CalcResultType::Date(*date),
+                token.index_into_tokens,
+            )),
             TokenType::NumberErr => {
-                return Err(());
+                return Err(CalcError::InvalidNumber);
             }
             TokenType::Unit(target_unit) => {
                 // TODO clone
@@ -127,18 +294,41 @@ pub fn evaluate_tokens<'text_ptr>(
                     assignment = true;
                     continue;
                 }
-                if apply_operation(tokens, &mut stack, &typ, token.index_into_tokens) == true {
-                    if matches!(typ, OperatorTokenType::UnitConverter) {
-                        there_was_unit_conversion = true;
-                    }
-                    if !stack.is_empty() {
-                        last_success_operation_result_index = Some(stack.len() - 1);
+                apply_operation(
+                    tokens,
+                    &mut stack,
+                    &typ,
+                    token.index_into_tokens,
+                    units,
+                    variables,
+                )?;
+                if matches!(typ, OperatorTokenType::UnitConverter) {
+                    there_was_unit_conversion = true;
+                    if let Some(top) = stack.last_mut() {
+                        top.unit_converted = true;
                     }
-                } else {
-                    return Err(());
+                }
+                if !stack.is_empty() {
+                    last_success_operation_result_index = Some(stack.len() - 1);
                 }
             }
             TokenType::StringLiteral => panic!(),
+            TokenType::LineRange { from, to } => {
+                // empty lines are skipped
+                let cells: Vec<CalcResult> = variables[*from..=*to]
+                    .iter()
+                    .filter_map(|var| var.as_ref().and_then(|var| var.value.as_ref().ok()))
+                    .map(|value| CalcResult::new(value.typ.clone(), token.index_into_tokens))
+                    .collect();
+                if cells.is_empty() {
+                    return Err(CalcError::EmptyLineRange);
+                }
+                let row_count = cells.len();
+                stack.push(CalcResult::new(
+                    CalcResultType::Matrix(MatrixData::new(cells, row_count, 1)),
+                    token.index_into_tokens,
+                ));
+            }
             TokenType::Variable { var_index } | TokenType::LineReference { var_index } => {
                 // TODO clone :(
                 match &variables[*var_index]
@@ -150,7 +340,7 @@ pub fn evaluate_tokens<'text_ptr>(
                         stack.push(CalcResult::new(value.typ.clone(), token.index_into_tokens));
                     }
                     Err(_) => {
-                        return Err(());
+                        return Err(CalcError::UnknownVariable);
                     }
                 }
             }
@@ -181,10 +371,45 @@ fn apply_operation<'text_ptr>(
     stack: &mut Vec<CalcResult>,
     op: &OperatorTokenType,
     op_token_index: usize,
-) -> bool {
+    units: &Units,
+    variables: &Variables,
+) -> Result<(), CalcError> {
+    // only the arithmetic operators can work with exact fractions
+    let decayed_operand_count = match &op {
+        OperatorTokenType::Fn { arg_count, .. } => *arg_count,
+        OperatorTokenType::Perc
+        | OperatorTokenType::BinNot
+        | OperatorTokenType::Not
+        | OperatorTokenType::ToBase(..)
+        | OperatorTokenType::AsInt(..)
+        | OperatorTokenType::ApplyUnit(..) => 1,
+        OperatorTokenType::BinAnd
+        | OperatorTokenType::BinOr
+        | OperatorTokenType::BinXor
+        | OperatorTokenType::ShiftLeft
+        | OperatorTokenType::ShiftRight
+        | OperatorTokenType::Less
+        | OperatorTokenType::LessOrEqual
+        | OperatorTokenType::Greater
+        | OperatorTokenType::GreaterOrEqual
+        | OperatorTokenType::Equal
+        | OperatorTokenType::NotEqual
+        | OperatorTokenType::And
+        | OperatorTokenType::Or
+        | OperatorTokenType::UnitConverter => 2,
+        _ => 0,
+    };
+    let from = stack.len().saturating_sub(decayed_operand_count);
+    for operand in stack[from..].iter_mut() {
+        if let Some(decayed) = decayed(operand) {
+            *operand = decayed;
+        }
+    }
     let succeed = match &op {
         OperatorTokenType::Mult
         | OperatorTokenType::Div
+        | OperatorTokenType::ElementwiseMult
+        | OperatorTokenType::ElementwiseDiv
         | OperatorTokenType::Add
         | OperatorTokenType::Sub
         | OperatorTokenType::BinAnd
@@ -193,10 +418,29 @@ fn apply_operation<'text_ptr>(
         | OperatorTokenType::Pow
         | OperatorTokenType::ShiftLeft
         | OperatorTokenType::ShiftRight
+        | OperatorTokenType::Less
+        | OperatorTokenType::LessOrEqual
+        | OperatorTokenType::Greater
+        | OperatorTokenType::GreaterOrEqual
+        | OperatorTokenType::Equal
+        | OperatorTokenType::NotEqual
+        | OperatorTokenType::And
+        | OperatorTokenType::Or
+        | OperatorTokenType::PercOf
+        | OperatorTokenType::PercOn
+        | OperatorTokenType::PercOff
+        | OperatorTokenType::IsWhatPercOf
+        | OperatorTokenType::IsPercOfWhat
+        | OperatorTokenType::PercChange
         | OperatorTokenType::UnitConverter => {
             if stack.len() > 1 {
                 let (lhs, rhs) = (&stack[stack.len() - 2], &stack[stack.len() - 1]);
-                if let Some(result) = binary_operation(op, lhs, rhs) {
+                let result = if can_exchange(lhs, rhs, units) {
+                    date_operation(op, lhs, rhs, units).or_else(|| binary_operation(op, lhs, rhs))
+                } else {
+                    None
+                };
+                if let Some(result) = result {
                     stack.truncate(stack.len() - 2);
                     stack.push(result);
                     true
@@ -204,7 +448,7 @@ fn apply_operation<'text_ptr>(
                     lhs.set_token_error_flag(tokens);
                     rhs.set_token_error_flag(tokens);
                     Token::set_token_error_flag_by_index(op_token_index, tokens);
-                    false
+                    return Err(binary_operation_error(op, lhs, rhs, units));
                 }
             } else {
                 false
@@ -213,7 +457,8 @@ fn apply_operation<'text_ptr>(
         OperatorTokenType::UnaryMinus
         | OperatorTokenType::UnaryPlus
         | OperatorTokenType::Perc
-        | OperatorTokenType::BinNot => {
+        | OperatorTokenType::BinNot
+        | OperatorTokenType::Not => {
             let maybe_top = stack.last();
             if let Some(result) =
                 maybe_top.and_then(|top| unary_operation(&op, top, op_token_index))
@@ -242,7 +487,47 @@ fn apply_operation<'text_ptr>(
             }
         }
         OperatorTokenType::Fn { arg_count, typ } => {
-            typ.execute(*arg_count, stack, op_token_index, tokens)
+            let (min, max) = typ.arg_count_range(variables);
+            if *arg_count < min || *arg_count > max {
+                Token::set_token_error_flag_by_index(op_token_index, tokens);
+                return Err(CalcError::WrongArgumentCount {
+                    min,
+                    max,
+                    got: *arg_count,
+                });
+            }
+            typ.execute(*arg_count, stack, op_token_index, tokens, units, variables)?;
+            true
+        }
+        OperatorTokenType::ToBase(radix) => {
+            // only integers can be shown in an other base
+            let maybe_top = stack
+                .last_mut()
+                .filter(|top| to_big_int(&top.typ).is_some());
+            if let Some(top) = maybe_top {
+                top.radix = Some(*radix);
+                true
+            } else {
+                Token::set_token_error_flag_by_index(op_token_index, tokens);
+                false
+            }
+        }
+        OperatorTokenType::AsInt(int_type) => {
+            let maybe_top = stack.last();
+            if let Some(result) = maybe_top.and_then(|top| {
+                fixed_width_int_result(
+                    to_big_int(&top.typ)?,
+                    Some(*int_type),
+                    top.index_into_tokens,
+                )
+            }) {
+                stack.pop();
+                stack.push(result);
+                true
+            } else {
+                Token::set_token_error_flag_by_index(op_token_index, tokens);
+                false
+            }
         }
         OperatorTokenType::Semicolon | OperatorTokenType::Comma => {
             // ignore
@@ -255,7 +540,7 @@ fn apply_operation<'text_ptr>(
         | OperatorTokenType::BracketClose => {
             // this branch was executed during fuzz testing, don't panic here
             // check test_panic_fuzz_3
-            return false;
+            false
         }
         OperatorTokenType::ApplyUnit(target_unit) => {
             let maybe_top = stack.last();
@@ -274,7 +559,170 @@ fn apply_operation<'text_ptr>(
             true
         }
     };
-    return succeed;
+    return if succeed {
+        Ok(())
+    } else {
+        Err(CalcError::InvalidOperation)
+    };
+}
+
+/// None if the value has no dimensions at all, e.g. a date or a matrix
+pub fn dimensions_of(
+    result: &CalcResult,
+) -> Option<[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT]> {
+    match &result.typ {
+        CalcResultType::Quantity(_, unit) | CalcResultType::Unit(unit) => Some(unit.dimensions),
+        CalcResultType::Number(_)
+        | CalcResultType::Percentage(_)
+        | CalcResultType::BigInt(_)
+        | CalcResultType::Rational(_) => Some(EMPTY_UNIT_DIMENSIONS),
+        _ => None,
+    }
+}
+
+fn binary_operation_error(
+    op: &OperatorTokenType,
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    units: &Units,
+) -> CalcError {
+    let rhs_is_zero = match &rhs.typ {
+        CalcResultType::Number(num)
+        | CalcResultType::Percentage(num)
+        | CalcResultType::Quantity(num, _) => num.is_zero(),
+        CalcResultType::BigInt(num) => *num == BigInt::from(0),
+        _ => false,
+    };
+    let is_div = matches!(
+        op,
+        OperatorTokenType::Div | OperatorTokenType::ElementwiseDiv
+    );
+    let needs_same_dimensions = matches!(
+        op,
+        OperatorTokenType::Add
+            | OperatorTokenType::Sub
+            | OperatorTokenType::Less
+            | OperatorTokenType::LessOrEqual
+            | OperatorTokenType::Greater
+            | OperatorTokenType::GreaterOrEqual
+            | OperatorTokenType::Equal
+            | OperatorTokenType::NotEqual
+            | OperatorTokenType::UnitConverter
+    );
+    let arithmetic = matches!(
+        op,
+        OperatorTokenType::Add
+            | OperatorTokenType::Sub
+            | OperatorTokenType::Mult
+            | OperatorTokenType::Div
+    );
+    // the operations are implemented for these, so only the checked arithmetic could fail
+    let is_number = |result: &CalcResult| {
+        matches!(
+            result.typ,
+            CalcResultType::Number(_)
+                | CalcResultType::BigInt(_)
+                | CalcResultType::Rational(_)
+                | CalcResultType::Quantity(..)
+        )
+    };
+    if !can_exchange(lhs, rhs, units) {
+        CalcError::MissingExchangeRate
+    } else if is_div && rhs_is_zero {
+        CalcError::DivisionByZero
+    } else if matches!(op, OperatorTokenType::Pow) {
+        pow_error(lhs, rhs)
+    } else {
+        match (dimensions_of(lhs), dimensions_of(rhs)) {
+            (Some(lhs_dim), Some(rhs_dim)) if needs_same_dimensions && lhs_dim != rhs_dim => {
+                CalcError::IncompatibleUnits(lhs_dim, rhs_dim)
+            }
+            _ if arithmetic && is_number(lhs) && is_number(rhs) => CalcError::Overflow,
+            _ => CalcError::InvalidOperation,
+        }
+    }
+}
+
+/// the exponent must be an integer without unit, e.g. "(2 m)^0.5" or "2^(1 m)" are invalid
+fn pow_error(lhs: &CalcResult, rhs: &CalcResult) -> CalcError {
+    let exp = match &rhs.typ {
+        CalcResultType::Number(exp) => exp,
+        CalcResultType::Quantity(_, unit) => {
+            return CalcError::IncompatibleUnits(unit.dimensions, EMPTY_UNIT_DIMENSIONS);
+        }
+        _ => return CalcError::InvalidOperation,
+    };
+    // only integer exponents are supported for these
+    let (base_is_zero, needs_integer_exp) = match &lhs.typ {
+        CalcResultType::Number(num) => (num.is_zero(), false),
+        CalcResultType::Quantity(num, _) => (num.is_zero(), true),
+        CalcResultType::Rational(_) | CalcResultType::Complex(..) => (false, true),
+        CalcResultType::BigInt(_) => (false, false),
+        _ => return CalcError::InvalidOperation,
+    };
+    if needs_integer_exp && exp.trunc() != *exp {
+        CalcError::InvalidOperation
+    } else if base_is_zero && exp.is_sign_negative() {
+        CalcError::DivisionByZero
+    } else {
+        CalcError::Overflow
+    }
+}
+
+/// e.g. 10 EUR + 5 USD or 10 EUR in USD needs a declared exchange rate
+fn can_exchange(lhs: &CalcResult, rhs: &CalcResult, units: &Units) -> bool {
+    fn unit_of(result: &CalcResult) -> Option<&UnitOutput> {
+        match &result.typ {
+            CalcResultType::Quantity(_, unit) | CalcResultType::Unit(unit) => Some(unit),
+            _ => None,
+        }
+    }
+    match (unit_of(lhs), unit_of(rhs)) {
+        (Some(lhs_unit), Some(rhs_unit)) => units.can_exchange(lhs_unit, rhs_unit),
+        _ => true,
+    }
+}
+
+/// e.g. today + 3 weeks or 2026-12-24 - today
+fn date_operation(
+    op: &OperatorTokenType,
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    units: &Units,
+) -> Option<CalcResult> {
+    let is_time = |unit: &UnitOutput| unit.dimensions == units.parse(&['s']).0.dimensions;
+    let date = match (op, &lhs.typ, &rhs.typ) {
+        (
+            OperatorTokenType::Add,
+            CalcResultType::Date(date),
+            CalcResultType::Quantity(secs, unit),
+        )
+        | (
+            OperatorTokenType::Add,
+            CalcResultType::Quantity(secs, unit),
+            CalcResultType::Date(date),
+        ) if is_time(unit) => date.checked_add_secs(secs)?,
+        (
+            OperatorTokenType::Sub,
+            CalcResultType::Date(date),
+            CalcResultType::Quantity(secs, unit),
+        ) if is_time(unit) => date.checked_add_secs(&secs.neg())?,
+        (OperatorTokenType::Sub, CalcResultType::Date(lhs), CalcResultType::Date(rhs)) => {
+            let secs = Decimal::from_i64(lhs.secs.checked_sub(rhs.secs)?)?;
+            // whole days are more readable for dates without time
+            let unit_name: &[char] = if lhs.has_time || rhs.has_time {
+                &['h']
+            } else {
+                &['d', 'a', 'y']
+            };
+            return Some(CalcResult::new(
+                CalcResultType::Quantity(secs, units.parse(unit_name).0),
+                0,
+            ));
+        }
+        _ => return None,
+    };
+    Some(CalcResult::new(CalcResultType::Date(date), 0))
 }
 
 fn unit_conversion(top: &CalcResult, target_unit: &UnitOutput) -> Option<CalcResult> {
@@ -290,6 +738,15 @@ fn unit_conversion(top: &CalcResult, target_unit: &UnitOutput) -> Option<CalcRes
                 })
             }
         }
+        CalcResultType::Complex(re, im, unit) if unit.units.is_empty() => {
+            // e.g. (3 + 4i) ohm
+            let re = target_unit.normalize(re)?;
+            let im = target_unit.normalize(im)?;
+            Some(CalcResult::new(
+                complex_result(re, im, target_unit.clone()),
+                0,
+            ))
+        }
         _ => None,
     }
 }
@@ -304,6 +761,10 @@ fn unary_operation(
         OperatorTokenType::UnaryMinus => unary_minus_op(top),
         OperatorTokenType::Perc => percentage_operator(top, op_token_index),
         OperatorTokenType::BinNot => binary_complement(top),
+        OperatorTokenType::Not => match &top.typ {
+            CalcResultType::Boolean(b) => Some(CalcResult::new(CalcResultType::Boolean(!b), 0)),
+            _ => None,
+        },
         _ => None,
     };
 }
@@ -315,7 +776,9 @@ fn binary_operation(
 ) -> Option<CalcResult> {
     let result = match &op {
         OperatorTokenType::Mult => multiply_op(lhs, rhs),
-        OperatorTokenType::Div => divide_op(lhs, rhs),
+        OperatorTokenType::Div => exact_divide_op(lhs, rhs),
+        OperatorTokenType::ElementwiseMult => elementwise_op(lhs, rhs, multiply_op),
+        OperatorTokenType::ElementwiseDiv => elementwise_op(lhs, rhs, divide_op),
         OperatorTokenType::Add => add_op(lhs, rhs),
         OperatorTokenType::Sub => sub_op(lhs, rhs),
         OperatorTokenType::BinAnd => binary_and_op(lhs, rhs),
@@ -324,6 +787,36 @@ fn binary_operation(
         OperatorTokenType::Pow => pow_op(lhs, rhs),
         OperatorTokenType::ShiftLeft => binary_shift_left(lhs, rhs),
         OperatorTokenType::ShiftRight => binary_shift_right(lhs, rhs),
+        OperatorTokenType::Less => comparison_op(lhs, rhs, |it| it == Ordering::Less),
+        OperatorTokenType::LessOrEqual => comparison_op(lhs, rhs, |it| it != Ordering::Greater),
+        OperatorTokenType::Greater => comparison_op(lhs, rhs, |it| it == Ordering::Greater),
+        OperatorTokenType::GreaterOrEqual => comparison_op(lhs, rhs, |it| it != Ordering::Less),
+        OperatorTokenType::Equal => equality_op(lhs, rhs).map(bool_result),
+        OperatorTokenType::NotEqual => equality_op(lhs, rhs).map(|it| bool_result(!it)),
+        OperatorTokenType::And => logical_op(lhs, rhs, |a, b| a && b),
+        OperatorTokenType::Or => logical_op(lhs, rhs, |a, b| a || b),
+        OperatorTokenType::PercOf => match &lhs.typ {
+            CalcResultType::Percentage(..) => multiply_op(lhs, rhs),
+            _ => None,
+        },
+        OperatorTokenType::PercOn => match &lhs.typ {
+            CalcResultType::Percentage(..) => add_op(rhs, lhs),
+            _ => None,
+        },
+        OperatorTokenType::PercOff => match &lhs.typ {
+            CalcResultType::Percentage(..) => sub_op(rhs, lhs),
+            _ => None,
+        },
+        OperatorTokenType::IsWhatPercOf => ratio_in_percentage(lhs, rhs),
+        OperatorTokenType::IsPercOfWhat => match &rhs.typ {
+            // 30 is 20% of what
+            CalcResultType::Percentage(perc) => divide_op(
+                lhs,
+                &CalcResult::new(CalcResultType::Number(perc.checked_div(&DECIMAL_100)?), 0),
+            ),
+            _ => None,
+        },
+        OperatorTokenType::PercChange => ratio_in_percentage(&sub_op(rhs, lhs)?, lhs),
         OperatorTokenType::UnitConverter => {
             return match (&lhs.typ, &rhs.typ) {
                 (
@@ -339,6 +832,19 @@ fn binary_operation(
                         None
                     }
                 }
+                (
+                    CalcResultType::Complex(re, im, source_unit),
+                    CalcResultType::Unit(target_unit),
+                ) => {
+                    if source_unit == target_unit {
+                        Some(CalcResult::new(
+                            CalcResultType::Complex(*re, *im, target_unit.clone()),
+                            0,
+                        ))
+                    } else {
+                        None
+                    }
+                }
                 (CalcResultType::Matrix(mat), CalcResultType::Unit(..)) => {
                     let cells: Option<Vec<CalcResult>> = mat
                         .cells
@@ -366,6 +872,18 @@ fn binary_operation(
     result
 }
 
+/// e.g. 30 is what % of 150
+fn ratio_in_percentage(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    let ratio = divide_op(lhs, rhs)?;
+    match decayed(&ratio).unwrap_or(ratio).typ {
+        CalcResultType::Number(ratio) => Some(CalcResult::new(
+            CalcResultType::Percentage(ratio.checked_mul(&DECIMAL_100)?),
+            0,
+        )),
+        _ => None,
+    }
+}
+
 fn percentage_operator(lhs: &CalcResult, op_token_index: usize) -> Option<CalcResult> {
     match &lhs.typ {
         CalcResultType::Number(lhs_num) => {
@@ -381,96 +899,52 @@ fn percentage_operator(lhs: &CalcResult, op_token_index: usize) -> Option<CalcRe
 }
 
 fn binary_complement(lhs: &CalcResult) -> Option<CalcResult> {
-    match &lhs.typ {
-        CalcResultType::Number(lhs_num) => {
-            // 0b01 and 0b10
-            let lhs_num = lhs_num.to_i64()?;
-            Some(CalcResult::new(
-                CalcResultType::Number(dec(lhs_num.not())),
-                lhs.index_into_tokens,
-            ))
-        }
-        _ => None,
-    }
+    // 0b01 and 0b10
+    let num = to_big_int(&lhs.typ)?;
+    fixed_width_int_result(-num - BigInt::from(1), lhs.int_type, lhs.index_into_tokens)
 }
 
 fn binary_xor_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
-    match (&lhs.typ, &rhs.typ) {
-        //////////////
-        // 12 and x
-        //////////////
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
-            // 0b01 and 0b10
-            let lhs = lhs.to_i64()?;
-            let rhs = rhs.to_i64()?;
-            Some(CalcResult::new(
-                CalcResultType::Number(dec(lhs.bitxor(rhs))),
-                0,
-            ))
-        }
-        _ => None,
-    }
+    int_op(lhs, rhs, |lhs, rhs| Some(lhs ^ rhs))
 }
 
 fn binary_or_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
-    match (&lhs.typ, &rhs.typ) {
-        //////////////
-        // 12 and x
-        //////////////
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
-            // 0b01 and 0b10
-            let lhs = lhs.to_i64()?;
-            let rhs = rhs.to_i64()?;
-            Some(CalcResult::new(CalcResultType::Number(dec(lhs | rhs)), 0))
-        }
-        _ => None,
-    }
+    int_op(lhs, rhs, |lhs, rhs| Some(lhs | rhs))
 }
 
 fn binary_shift_right(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
-    match (&lhs.typ, &rhs.typ) {
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
-            let lhs = lhs.to_i64()?;
-            let rhs = rhs.to_u32()?;
-            Some(CalcResult::new(
-                CalcResultType::Number(dec(lhs.wrapping_shr(rhs))),
-                0,
-            ))
-        }
-        _ => None,
-    }
+    let int_type = lhs.int_type;
+    let lhs = to_big_int(&lhs.typ)?;
+    let rhs = shift_amount(rhs)?;
+    fixed_width_int_result(lhs >> rhs, int_type, 0)
 }
 
 fn binary_shift_left(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
-    match (&lhs.typ, &rhs.typ) {
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
-            let lhs = lhs.to_i64()?;
-            let rhs = rhs.to_u32()?;
-            Some(CalcResult::new(
-                CalcResultType::Number(dec(lhs.wrapping_shl(rhs))),
-                0,
-            ))
+    let int_type = lhs.int_type;
+    let lhs = to_big_int(&lhs.typ)?;
+    let rhs = shift_amount(rhs)?;
+    fixed_width_int_result(lhs << rhs, int_type, 0)
+}
+
+fn shift_amount(rhs: &CalcResult) -> Option<usize> {
+    match &rhs.typ {
+        CalcResultType::Number(rhs) => {
+            let rhs = rhs.to_u32()? as usize;
+            if rhs as u64 > MAX_INT_BITS {
+                None
+            } else {
+                Some(rhs)
+            }
         }
         _ => None,
     }
 }
 
 fn binary_and_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
-    match (&lhs.typ, &rhs.typ) {
-        //////////////
-        // 12 and x
-        //////////////
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
-            // 0b01 and 0b10
-            let lhs = lhs.to_i64()?;
-            let rhs = rhs.to_i64()?;
-            Some(CalcResult::new(CalcResultType::Number(dec(lhs & rhs)), 0))
-        }
-        _ => None,
-    }
+    int_op(lhs, rhs, |lhs, rhs| Some(lhs & rhs))
 }
 
-fn unary_minus_op(lhs: &CalcResult) -> Option<CalcResult> {
+pub fn unary_minus_op(lhs: &CalcResult) -> Option<CalcResult> {
     match &lhs.typ {
         CalcResultType::Number(lhs_num) => {
             // -12
@@ -493,12 +967,54 @@ fn unary_minus_op(lhs: &CalcResult) -> Option<CalcResult> {
                 lhs.index_into_tokens,
             ))
         }
-        _ => None, // CalcResultType::Matrix(mat) => CalcResultType::Matrix(mat.neg()),
+        CalcResultType::Matrix(mat) => {
+            // -[1, 2]
+            Some(CalcResult::new(
+                CalcResultType::Matrix(mat.neg()?),
+                lhs.index_into_tokens,
+            ))
+        }
+        CalcResultType::BigInt(num) => Some(CalcResult::new(
+            int_result(-num.clone())?,
+            lhs.index_into_tokens,
+        )),
+        CalcResultType::Complex(re, im, unit) => Some(CalcResult::new(
+            CalcResultType::Complex(re.neg(), im.neg(), unit.clone()),
+            lhs.index_into_tokens,
+        )),
+        CalcResultType::Rational(r) => Some(CalcResult::new(
+            CalcResultType::Rational(r.neg()),
+            lhs.index_into_tokens,
+        )),
+        CalcResultType::Unit(..) | CalcResultType::Boolean(..) | CalcResultType::Date(..) => None,
     }
 }
 
 fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Rational(r), CalcResultType::Number(exp)) => {
+            // (1/3)^2, fractional exponents would need roots of the fraction
+            if exp.trunc() != *exp {
+                return None;
+            }
+            match r.pow(exp.to_i64()?) {
+                Some(p) => Some(CalcResult::new(rational_result(p)?, 0)),
+                None => pow_op(&decayed(lhs)?, rhs),
+            }
+        }
+        (CalcResultType::Rational(..), _) | (_, CalcResultType::Rational(..)) => {
+            let (lhs, rhs) = decayed_pair(lhs, rhs)?;
+            pow_op(&lhs, &rhs)
+        }
+        (CalcResultType::Complex(re, im, unit), CalcResultType::Number(rhs)) => {
+            // (1 + 2i)^2, fractional exponents would need the principal root
+            if rhs.trunc() != *rhs {
+                return None;
+            }
+            let p = rhs.to_i64()?;
+            let (re, im) = complex_pow((*re, *im), p)?;
+            Some(CalcResult::new(complex_result(re, im, unit.pow(p)?), 0))
+        }
         //////////////
         // 1^x
         //////////////
@@ -512,6 +1028,10 @@ fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 .map(|pow| CalcResult::new(CalcResultType::Number(pow), 0))
         }
         (CalcResultType::Quantity(lhs, lhs_unit), CalcResultType::Number(rhs)) => {
+            // units can't have fractional exponents
+            if rhs.trunc() != *rhs {
+                return None;
+            }
             let p = rhs.to_i64()?;
             let num_powered = pow(lhs.clone(), p)?;
             let unit_powered = lhs_unit.pow(p);
@@ -526,6 +1046,19 @@ fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
 
 pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     let result = match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(..), _)
+        | (_, CalcResultType::Boolean(..))
+        | (CalcResultType::Date(..), _)
+        | (_, CalcResultType::Date(..)) => None,
+        (CalcResultType::Rational(..), _) | (_, CalcResultType::Rational(..)) => {
+            rational_op(lhs, rhs, Rational::checked_mul, multiply_op)
+        }
+        (CalcResultType::BigInt(..), _) | (_, CalcResultType::BigInt(..)) => {
+            int_op(lhs, rhs, |lhs, rhs| Some(lhs * rhs))
+        }
+        (CalcResultType::Complex(..), _) | (_, CalcResultType::Complex(..)) => {
+            complex_op(lhs, rhs, complex_mul, |lhs, rhs| Some(lhs * rhs))
+        }
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
         | (CalcResultType::Unit(..), CalcResultType::Quantity(..))
@@ -645,13 +1178,8 @@ pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                         return None;
                     };
                     for i in 1..a.col_count {
-                        if let Some(r) = multiply_op(a.cell(row, i), b.cell(i, col)) {
-                            if let Some(s) = add_op(&sum, &r) {
-                                sum = s;
-                            } else {
-                                return None;
-                            }
-                        }
+                        let r = multiply_op(a.cell(row, i), b.cell(i, col))?;
+                        sum = add_op(&sum, &r)?;
                     }
                     result.push(sum);
                 }
@@ -676,6 +1204,22 @@ pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
 
 pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(..), _)
+        | (_, CalcResultType::Boolean(..))
+        | (CalcResultType::Date(..), _)
+        | (_, CalcResultType::Date(..)) => None,
+        (CalcResultType::Rational(..), _) | (_, CalcResultType::Rational(..)) => {
+            rational_op(lhs, rhs, Rational::checked_add, add_op)
+        }
+        (CalcResultType::BigInt(..), _) | (_, CalcResultType::BigInt(..)) => {
+            int_op(lhs, rhs, |lhs, rhs| Some(lhs + rhs))
+        }
+        (CalcResultType::Complex(..), _) | (_, CalcResultType::Complex(..)) => complex_op(
+            lhs,
+            rhs,
+            |(a, b), (c, d)| Some((a.checked_add(&c)?, b.checked_add(&d)?)),
+            same_unit,
+        ),
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
         | (CalcResultType::Unit(..), CalcResultType::Quantity(..))
@@ -709,7 +1253,10 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Number(_lhs), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Number(..), CalcResultType::Matrix(rhs_mat)) => {
+            // 12 + [1, 2]
+            rhs_mat.map_cells(|cell| add_op(lhs, cell))
+        }
         //////////////
         // 12km + x
         //////////////
@@ -738,7 +1285,9 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Quantity(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Quantity(..), CalcResultType::Matrix(rhs_mat)) => {
+            rhs_mat.map_cells(|cell| add_op(lhs, cell))
+        }
         //////////////
         // 12% + x
         //////////////
@@ -754,35 +1303,40 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
             // 50% + 50%
             Some(CalcResult::new(CalcResultType::Percentage(lhs + rhs), 0))
         }
-        (CalcResultType::Percentage(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Percentage(..), CalcResultType::Matrix(rhs_mat)) => {
+            rhs_mat.map_cells(|cell| add_op(lhs, cell))
+        }
         ///////////
         // Matrix
         //////////
-        (CalcResultType::Matrix(..), CalcResultType::Number(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Quantity(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Percentage(..)) => None,
-        (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => {
-            if lhs.row_count != rhs.row_count || lhs.col_count != rhs.col_count {
-                return None;
-            }
-            let cells: Option<Vec<CalcResult>> = lhs
-                .cells
-                .iter()
-                .zip(rhs.cells.iter())
-                .map(|(a, b)| add_op(a, b))
-                .collect();
-            cells.map(|it| {
-                CalcResult::new(
-                    CalcResultType::Matrix(MatrixData::new(it, lhs.row_count, lhs.col_count)),
-                    0,
-                )
-            })
+        (CalcResultType::Matrix(lhs_mat), CalcResultType::Number(..))
+        | (CalcResultType::Matrix(lhs_mat), CalcResultType::Quantity(..))
+        | (CalcResultType::Matrix(lhs_mat), CalcResultType::Percentage(..)) => {
+            // [1, 2] + 12
+            lhs_mat.map_cells(|cell| add_op(cell, rhs))
         }
+        (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => lhs.zip_cells(rhs, add_op),
     }
 }
 
-fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+pub fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(..), _)
+        | (_, CalcResultType::Boolean(..))
+        | (CalcResultType::Date(..), _)
+        | (_, CalcResultType::Date(..)) => None,
+        (CalcResultType::Rational(..), _) | (_, CalcResultType::Rational(..)) => {
+            rational_op(lhs, rhs, Rational::checked_sub, sub_op)
+        }
+        (CalcResultType::BigInt(..), _) | (_, CalcResultType::BigInt(..)) => {
+            int_op(lhs, rhs, |lhs, rhs| Some(lhs - rhs))
+        }
+        (CalcResultType::Complex(..), _) | (_, CalcResultType::Complex(..)) => complex_op(
+            lhs,
+            rhs,
+            |(a, b), (c, d)| Some((a.checked_sub(&c)?, b.checked_sub(&d)?)),
+            same_unit,
+        ),
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
         | (CalcResultType::Unit(..), CalcResultType::Quantity(..))
@@ -816,7 +1370,10 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Number(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Number(..), CalcResultType::Matrix(rhs_mat)) => {
+            // 12 - [1, 2]
+            rhs_mat.map_cells(|cell| sub_op(lhs, cell))
+        }
         //////////////
         // 12km - x
         //////////////
@@ -848,7 +1405,9 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Quantity(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Quantity(..), CalcResultType::Matrix(rhs_mat)) => {
+            rhs_mat.map_cells(|cell| sub_op(lhs, cell))
+        }
         //////////////
         // 12% - x
         //////////////
@@ -867,35 +1426,63 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Percentage(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Percentage(..), CalcResultType::Matrix(rhs_mat)) => {
+            rhs_mat.map_cells(|cell| sub_op(lhs, cell))
+        }
         ///////////
         // Matrix
         //////////
-        (CalcResultType::Matrix(..), CalcResultType::Number(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Quantity(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Percentage(..)) => None,
-        (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => {
-            if lhs.row_count != rhs.row_count || lhs.col_count != rhs.col_count {
-                return None;
-            }
-            let cells: Option<Vec<CalcResult>> = lhs
-                .cells
-                .iter()
-                .zip(rhs.cells.iter())
-                .map(|(a, b)| sub_op(a, b))
-                .collect();
-            cells.map(|it| {
-                CalcResult::new(
-                    CalcResultType::Matrix(MatrixData::new(it, lhs.row_count, lhs.col_count)),
-                    0,
-                )
-            })
+        (CalcResultType::Matrix(lhs_mat), CalcResultType::Number(..))
+        | (CalcResultType::Matrix(lhs_mat), CalcResultType::Quantity(..))
+        | (CalcResultType::Matrix(lhs_mat), CalcResultType::Percentage(..)) => {
+            // [1, 2] - 12
+            lhs_mat.map_cells(|cell| sub_op(cell, rhs))
         }
+        (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => lhs.zip_cells(rhs, sub_op),
+    }
+}
+
+/// The '/' operator keeps the quotient of two numbers as an exact fraction
+/// if a Decimal can't represent it, e.g. 1/3.
+/// Functions and matrix operations divide Decimals with `divide_op`.
+fn exact_divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    if let (CalcResultType::Number(lhs_num), CalcResultType::Number(rhs_num)) = (&lhs.typ, &rhs.typ)
+    {
+        let num = lhs_num.checked_div(rhs_num)?;
+        let exact = Rational::from_decimal(lhs_num)
+            .and_then(|lhs| lhs.checked_div(&Rational::from_decimal(rhs_num)?))
+            .and_then(rational_result);
+        Some(CalcResult::new(
+            exact.unwrap_or(CalcResultType::Number(num)),
+            0,
+        ))
+    } else {
+        divide_op(lhs, rhs)
     }
 }
 
 pub fn divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     let result: Option<CalcResult> = match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(..), _)
+        | (_, CalcResultType::Boolean(..))
+        | (CalcResultType::Date(..), _)
+        | (_, CalcResultType::Date(..)) => None,
+        (CalcResultType::Rational(..), _) | (_, CalcResultType::Rational(..)) => {
+            rational_op(lhs, rhs, Rational::checked_div, divide_op)
+        }
+        (CalcResultType::BigInt(..), _) | (_, CalcResultType::BigInt(..)) => {
+            // only exact divisions, the result could not be represented otherwise
+            int_op(lhs, rhs, |lhs, rhs| {
+                if rhs == BigInt::from(0) || &lhs % &rhs != BigInt::from(0) {
+                    None
+                } else {
+                    Some(lhs / rhs)
+                }
+            })
+        }
+        (CalcResultType::Complex(..), _) | (_, CalcResultType::Complex(..)) => {
+            complex_op(lhs, rhs, complex_div, |lhs, rhs| Some(lhs / rhs))
+        }
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
         | (CalcResultType::Unit(..), CalcResultType::Quantity(..))
@@ -1035,6 +1622,92 @@ pub fn divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     };
 }
 
+fn bool_result(b: bool) -> CalcResult {
+    CalcResult::new(CalcResultType::Boolean(b), 0)
+}
+
+/// Percentages are compared as fractions, so 0.85 > 80% is true
+fn compare_for_condition(lhs: &CalcResult, rhs: &CalcResult) -> Option<Ordering> {
+    return match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(num), CalcResultType::Percentage(perc)) => {
+            Some(num.cmp(&perc.checked_div(&DECIMAL_100)?))
+        }
+        (CalcResultType::Percentage(perc), CalcResultType::Number(num)) => {
+            Some(perc.checked_div(&DECIMAL_100)?.cmp(num))
+        }
+        _ => compare_values(lhs, rhs),
+    };
+}
+
+fn comparison_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    f: fn(Ordering) -> bool,
+) -> Option<CalcResult> {
+    compare_for_condition(lhs, rhs).map(|it| bool_result(f(it)))
+}
+
+fn equality_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<bool> {
+    return match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(lhs), CalcResultType::Boolean(rhs)) => Some(lhs == rhs),
+        (CalcResultType::Complex(..), _) | (_, CalcResultType::Complex(..)) => {
+            let (lhs_re, lhs_im, lhs_unit) = to_complex(&lhs.typ)?;
+            let (rhs_re, rhs_im, rhs_unit) = to_complex(&rhs.typ)?;
+            if lhs_unit != rhs_unit {
+                None
+            } else {
+                Some(lhs_re == rhs_re && lhs_im == rhs_im)
+            }
+        }
+        _ => compare_for_condition(lhs, rhs).map(|it| it == Ordering::Equal),
+    };
+}
+
+fn logical_op(lhs: &CalcResult, rhs: &CalcResult, f: fn(bool, bool) -> bool) -> Option<CalcResult> {
+    return match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Boolean(lhs), CalcResultType::Boolean(rhs)) => {
+            Some(bool_result(f(*lhs, *rhs)))
+        }
+        _ => None,
+    };
+}
+
+/// e.g. [1, 2] .* [3, 4], scalars are applied on each cell of the matrix
+fn elementwise_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    op: fn(&CalcResult, &CalcResult) -> Option<CalcResult>,
+) -> Option<CalcResult> {
+    return match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Unit(..), _) | (_, CalcResultType::Unit(..)) => None,
+        (CalcResultType::Matrix(lhs_mat), CalcResultType::Matrix(rhs_mat)) => {
+            lhs_mat.zip_cells(rhs_mat, op)
+        }
+        (CalcResultType::Matrix(lhs_mat), _) => lhs_mat.map_cells(|cell| op(cell, rhs)),
+        (_, CalcResultType::Matrix(rhs_mat)) => rhs_mat.map_cells(|cell| op(lhs, cell)),
+        _ => op(lhs, rhs),
+    };
+}
+
+/// None if the values are not comparable, e.g. 1 m and 1 kg
+pub fn compare_values(lhs: &CalcResult, rhs: &CalcResult) -> Option<Ordering> {
+    return match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(lhs), CalcResultType::Number(rhs))
+        | (CalcResultType::Percentage(lhs), CalcResultType::Percentage(rhs)) => Some(lhs.cmp(rhs)),
+        (CalcResultType::Quantity(lhs, lhs_unit), CalcResultType::Quantity(rhs, rhs_unit))
+            if lhs_unit == rhs_unit =>
+        {
+            // both are stored in base unit, e.g. 3 m and 250 cm
+            Some(lhs.cmp(rhs))
+        }
+        (CalcResultType::BigInt(..), _) | (_, CalcResultType::BigInt(..)) => {
+            Some(to_big_int(&lhs.typ)?.cmp(&to_big_int(&rhs.typ)?))
+        }
+        (CalcResultType::Date(lhs), CalcResultType::Date(rhs)) => Some(lhs.secs.cmp(&rhs.secs)),
+        _ => None,
+    };
+}
+
 pub fn pow(this: Decimal, mut exp: i64) -> Option<Decimal> {
     if this.is_zero() && exp.is_negative() {
         return None;
@@ -1069,6 +1742,255 @@ pub fn dec(num: i64) -> Decimal {
     Decimal::from_i64(num).unwrap()
 }
 
+/// Larger integers are rejected, e.g. their binary form would not fit into the result panel
+pub const MAX_INT_BITS: u64 = 256;
+
+pub fn to_big_int(typ: &CalcResultType) -> Option<BigInt> {
+    match typ {
+        CalcResultType::Number(num) if num.trunc() == *num => {
+            BigInt::from_str(&num.trunc().to_string()).ok()
+        }
+        CalcResultType::BigInt(num) => Some(num.clone()),
+        _ => None,
+    }
+}
+
+/// Integers are stored as Decimal if they fit into it, so all the other operations can work with them
+pub fn int_result(num: BigInt) -> Option<CalcResultType> {
+    if num.bits() <= 96 {
+        Decimal::from_str(&num.to_string())
+            .ok()
+            .map(CalcResultType::Number)
+    } else if num.bits() <= MAX_INT_BITS {
+        Some(CalcResultType::BigInt(num))
+    } else {
+        None
+    }
+}
+
+fn int_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    f: fn(BigInt, BigInt) -> Option<BigInt>,
+) -> Option<CalcResult> {
+    let int_type = lhs.int_type.or(rhs.int_type);
+    let lhs = to_big_int(&lhs.typ)?;
+    let rhs = to_big_int(&rhs.typ)?;
+    fixed_width_int_result(f(lhs, rhs)?, int_type, 0)
+}
+
+/// The value is wrapped around if it does not fit into the given width
+fn fixed_width_int_result(
+    num: BigInt,
+    int_type: Option<IntType>,
+    index: usize,
+) -> Option<CalcResult> {
+    let num = match int_type {
+        Some(int_type) => int_type.wrap(&num),
+        None => num,
+    };
+    let mut result = CalcResult::new(int_result(num)?, index);
+    result.int_type = int_type;
+    Some(result)
+}
+
+pub fn to_rational(typ: &CalcResultType) -> Option<Rational> {
+    match typ {
+        CalcResultType::Number(num) => Rational::from_decimal(num),
+        CalcResultType::BigInt(num) => Rational::new(num.clone(), BigInt::from(1)),
+        CalcResultType::Rational(r) => Some(r.clone()),
+        _ => None,
+    }
+}
+
+/// Fractions are kept only when a Decimal can't represent them exactly, e.g. 1/4 is 0.25
+pub fn rational_result(r: Rational) -> Option<CalcResultType> {
+    if r.is_integer() {
+        return int_result(r.numer);
+    }
+    let num = r.to_decimal()?;
+    if Rational::from_decimal(&num).as_ref() == Some(&r) {
+        Some(CalcResultType::Number(num))
+    } else if r.numer.bits() <= 96 && r.denom.bits() <= 96 {
+        Some(CalcResultType::Rational(r))
+    } else {
+        Some(CalcResultType::Number(num))
+    }
+}
+
+/// Converts the fractions into Decimals for the operations which can't handle them
+pub fn decayed(result: &CalcResult) -> Option<CalcResult> {
+    match &result.typ {
+        CalcResultType::Rational(r) => Some(CalcResult {
+            typ: CalcResultType::Number(r.to_decimal()?),
+            ..result.clone()
+        }),
+        CalcResultType::Matrix(mat)
+            if mat
+                .cells
+                .iter()
+                .any(|it| matches!(it.typ, CalcResultType::Rational(..))) =>
+        {
+            let cells = mat
+                .cells
+                .iter()
+                .map(|it| decayed(it).unwrap_or_else(|| it.clone()))
+                .collect();
+            Some(CalcResult {
+                typ: CalcResultType::Matrix(MatrixData::new(cells, mat.row_count, mat.col_count)),
+                ..result.clone()
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Both operands have to be exact for an exact result, e.g. 1/3 * 3 but not 1/3 * 3m
+fn rational_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    f: fn(&Rational, &Rational) -> Option<Rational>,
+    op: fn(&CalcResult, &CalcResult) -> Option<CalcResult>,
+) -> Option<CalcResult> {
+    match (to_rational(&lhs.typ), to_rational(&rhs.typ)) {
+        (Some(lhs), Some(rhs)) => Some(CalcResult::new(rational_result(f(&lhs, &rhs)?)?, 0)),
+        _ => {
+            let (lhs, rhs) = decayed_pair(lhs, rhs)?;
+            op(&lhs, &rhs)
+        }
+    }
+}
+
+fn decayed_pair(lhs: &CalcResult, rhs: &CalcResult) -> Option<(CalcResult, CalcResult)> {
+    let lhs = decayed(lhs).unwrap_or_else(|| lhs.clone());
+    let rhs = decayed(rhs).unwrap_or_else(|| rhs.clone());
+    // avoid endless recursion if the fraction could not be converted
+    if matches!(lhs.typ, CalcResultType::Rational(..))
+        || matches!(rhs.typ, CalcResultType::Rational(..))
+    {
+        None
+    } else {
+        Some((lhs, rhs))
+    }
+}
+
+/// Real values are treated as complex numbers with zero imaginary part
+pub fn to_complex(typ: &CalcResultType) -> Option<(Decimal, Decimal, UnitOutput)> {
+    match typ {
+        CalcResultType::Number(num) => Some((*num, Decimal::zero(), UnitOutput::new())),
+        CalcResultType::Quantity(num, unit) => Some((*num, Decimal::zero(), unit.clone())),
+        CalcResultType::Complex(re, im, unit) => Some((*re, *im, unit.clone())),
+        _ => None,
+    }
+}
+
+/// The imaginary part might have been cancelled out, e.g. (1 + 2i) - 2i
+pub fn complex_result(re: Decimal, im: Decimal, unit: UnitOutput) -> CalcResultType {
+    let unit = if unit.is_unitless() {
+        UnitOutput::new()
+    } else {
+        unit
+    };
+    if !im.is_zero() {
+        CalcResultType::Complex(re, im, unit)
+    } else if unit.units.is_empty() {
+        CalcResultType::Number(re)
+    } else {
+        CalcResultType::Quantity(re, unit)
+    }
+}
+
+fn complex_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    f: fn((Decimal, Decimal), (Decimal, Decimal)) -> Option<(Decimal, Decimal)>,
+    unit_op: fn(&UnitOutput, &UnitOutput) -> Option<UnitOutput>,
+) -> Option<CalcResult> {
+    let (lhs_re, lhs_im, lhs_unit) = to_complex(&lhs.typ)?;
+    let (rhs_re, rhs_im, rhs_unit) = to_complex(&rhs.typ)?;
+    let unit = unit_op(&lhs_unit, &rhs_unit)?;
+    let (re, im) = f((lhs_re, lhs_im), (rhs_re, rhs_im))?;
+    Some(CalcResult::new(complex_result(re, im, unit), 0))
+}
+
+fn same_unit(lhs: &UnitOutput, rhs: &UnitOutput) -> Option<UnitOutput> {
+    if lhs == rhs {
+        Some(lhs.clone())
+    } else {
+        None
+    }
+}
+
+fn complex_mul(
+    (a, b): (Decimal, Decimal),
+    (c, d): (Decimal, Decimal),
+) -> Option<(Decimal, Decimal)> {
+    // (a + bi)(c + di) = (ac - bd) + (ad + bc)i
+    let re = a.checked_mul(&c)?.checked_sub(&b.checked_mul(&d)?)?;
+    let im = a.checked_mul(&d)?.checked_add(&b.checked_mul(&c)?)?;
+    Some((re, im))
+}
+
+fn complex_div(
+    (a, b): (Decimal, Decimal),
+    (c, d): (Decimal, Decimal),
+) -> Option<(Decimal, Decimal)> {
+    // (a + bi)/(c + di) = ((ac + bd) + (bc - ad)i) / (c^2 + d^2)
+    let denom = c.checked_mul(&c)?.checked_add(&d.checked_mul(&d)?)?;
+    let re = a.checked_mul(&c)?.checked_add(&b.checked_mul(&d)?)?;
+    let im = b.checked_mul(&c)?.checked_sub(&a.checked_mul(&d)?)?;
+    Some((re.checked_div(&denom)?, im.checked_div(&denom)?))
+}
+
+/// |re + im*i|, the f64 estimate is refined with Newton's method to keep the precision of Decimal
+pub fn complex_abs(re: &Decimal, im: &Decimal) -> Option<Decimal> {
+    let estimate = Decimal::from_f64(re.to_f64()?.hypot(im.to_f64()?))?;
+    let square = re
+        .checked_mul(re)
+        .zip(im.checked_mul(im))
+        .and_then(|(re2, im2)| re2.checked_add(&im2));
+    let square = if let Some(square) = square {
+        square
+    } else {
+        // too big for Decimal, the estimate is the best we can do
+        return Some(estimate);
+    };
+    let mut abs = estimate;
+    let two = Decimal::from(2);
+    for _ in 0..8 {
+        if abs.is_zero() {
+            break;
+        }
+        let next = abs
+            .checked_add(&square.checked_div(&abs)?)?
+            .checked_div(&two)?;
+        if next == abs {
+            break;
+        }
+        abs = next;
+    }
+    Some(abs)
+}
+
+fn complex_pow(mut base: (Decimal, Decimal), exp: i64) -> Option<(Decimal, Decimal)> {
+    let mut acc = (Decimal::one(), Decimal::zero());
+    let mut n = exp.abs();
+    while n > 0 {
+        if (n & 1) == 1 {
+            acc = complex_mul(acc, base)?;
+        }
+        n /= 2;
+        if n > 0 {
+            base = complex_mul(base, base)?;
+        }
+    }
+    if exp < 0 {
+        complex_div((Decimal::one(), Decimal::zero()), acc)
+    } else {
+        Some(acc)
+    }
+}
+
 const DECIMAL_100: Decimal = Decimal::from_parts(100, 0, 0, false, 0);
 
 fn percentage_of(this: &Decimal, base: &Decimal) -> Option<Decimal> {
@@ -1081,10 +2003,10 @@ mod tests {
         apply_to_prev_token_unit, num, num_with_err, op, op_err, str, unit,
     };
     use crate::units::units::Units;
-    use crate::{ResultFormat, Variable, Variables};
+    use crate::{ResultFormat, ResultPrecision, Variable, Variables};
     use std::str::FromStr;
 
-    use crate::calc::{CalcResult, CalcResultType, EvaluationResult};
+    use crate::calc::{CalcError, CalcResult, CalcResultType, EvaluationResult};
     use crate::functions::FnType;
     use crate::helper::create_vars;
     use crate::renderer::render_result;
@@ -1109,7 +2031,8 @@ mod tests {
             &vars,
             &arena,
         );
-        let _result_stack = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, &vars);
+        let _result_stack =
+            crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, &vars, &units);
 
         crate::shunting_yard::tests::compare_tokens(expected_tokens, &tokens);
     }
@@ -1126,7 +2049,7 @@ mod tests {
         let mut shunting_output =
             crate::shunting_yard::tests::do_shunting_yard(&temp, &units, &mut tokens, vars, &arena);
 
-        let result = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, vars);
+        let result = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, vars, &units);
 
         if let Err(..) = &result {
             assert_eq!("Err", expected);
@@ -1147,7 +2070,7 @@ mod tests {
                     &result.as_ref().unwrap().as_ref().unwrap().result,
                     &ResultFormat::Dec,
                     *there_was_unit_conversion,
-                    Some(dec_count),
+                    Some(ResultPrecision::DecimalPlaces(dec_count)),
                     false,
                 ),
                 expected
@@ -1161,7 +2084,7 @@ mod tests {
                         &it.result,
                         &ResultFormat::Dec,
                         false,
-                        Some(dec_count),
+                        Some(ResultPrecision::DecimalPlaces(dec_count)),
                         false
                     ))
                     .unwrap_or(" ".to_string()),
@@ -1466,6 +2389,48 @@ mod tests {
         test_with_dec_count(40, "128PiB / 30Mb/s", "38430716586.666666666666666666 s");
     }
 
+    #[test]
+    fn test_simplify_to_named_derived_units() {
+        test("10 J / 2 s", "5 W");
+        test("10 W / 2 A", "5 V");
+        test("10 N / 2 m^2", "5 Pa");
+        test("10 V * 2 A", "20 W");
+        // explicit conversion keeps the requested unit
+        test("10 W / 2 A in W/A", "5 W / A");
+    }
+
+    #[test]
+    fn test_keep_raw_units() {
+        let temp = "9.81 kg*m/s^2 * 1".chars().collect::<Vec<char>>();
+        let units = Units::new();
+        units.set_keep_raw_units(true);
+        let vars = create_vars();
+        let mut tokens = vec![];
+        let arena = Bump::new();
+        let mut shunting_output = crate::shunting_yard::tests::do_shunting_yard(
+            &temp,
+            &units,
+            &mut tokens,
+            &vars,
+            &arena,
+        );
+        let result = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, &vars, &units)
+            .ok()
+            .flatten()
+            .expect("it should be valid");
+        assert_eq!(
+            render_result(
+                &units,
+                &result.result,
+                &ResultFormat::Dec,
+                false,
+                Some(ResultPrecision::DecimalPlaces(DECIMAL_COUNT)),
+                false,
+            ),
+            "9.81 (kg m) / s^2"
+        );
+    }
+
     #[test]
     fn unit_calcs() {
         test_with_dec_count(5, "50km + 50mm", "50.00005 km");
@@ -1528,6 +2493,40 @@ mod tests {
 
         test("2 km + [3]", "Err");
         test("[2 km] + [3]", "Err");
+        test("[2, 3] + [4, 5, 6]", "Err");
+        test("[2, 3] + [4; 5]", "Err");
+        test("[2 m, 3 m] + [4 cm, 5 cm]", "[2.04 m, 3.05 m]");
+    }
+
+    #[test]
+    fn test_matrix_scalar_addition() {
+        test("10 + [2, 3]", "[12, 13]");
+        test("[2, 3] + 10", "[12, 13]");
+        test("[2; 3] - 1", "[1; 2]");
+        test("10 - [2, 3]", "[8, 7]");
+        test("1 m + [2 cm, 3 m]", "[1.02 m, 4 m]");
+        test("[100, 200] + 10%", "[110, 220]");
+    }
+
+    #[test]
+    fn test_matrix_neg() {
+        test("-[1, 2, 3]", "[-1, -2, -3]");
+        test("-[1 m, -2 m; 3 m, 4 m]", "[-1 m, 2 m; -3 m, -4 m]");
+    }
+
+    #[test]
+    fn test_matrix_elementwise_mult_div() {
+        test("[2, 3] .* [4, 5]", "[8, 15]");
+        test("[2, 3; 4, 5] .* [2, 3; 4, 5]", "[4, 9; 16, 25]");
+        test("[8, 15] ./ [4, 5]", "[2, 3]");
+        test("[2 m, 3 m] .* [4, 5]", "[8 m, 15 m]");
+        test("[3m] .* [2cm]", "[0.06 m^2]");
+        test("[6 m, 10 m] ./ [2 m, 500 cm]", "[3, 2]");
+        test("12 ./ [2, 3]", "[6, 4]");
+        test("[2, 3] .* 2", "[4, 6]");
+        test("[2, 3] .* [4, 5, 6]", "Err");
+        test("[2, 3] ./ [1, 0]", "Err");
+        test("3 .* 4", "12");
     }
 
     #[test]
@@ -1595,6 +2594,48 @@ mod tests {
         test("[2,3] * [4]", "Err");
     }
 
+    #[test]
+    fn test_matrix_fn_det_trace_rank() {
+        test("det([1, 2; 3, 4])", "-2");
+        test("det([2, 0, 0; 0, 3, 0; 0, 0, 4])", "24");
+        test("det([1, 2; 2, 4])", "0");
+        test("det([2 m, 0 m; 0 m, 3 m])", "6 m^2");
+        test("det([1, 2, 3])", "Err");
+        test("det(3)", "Err");
+
+        test("trace([1, 2; 3, 4])", "5");
+        test("trace([1 m, 2 m; 3 m, 40 cm])", "1.4 m");
+        test("trace([1, 2])", "Err");
+
+        test("rank([1, 2; 3, 4])", "2");
+        test("rank([1, 2; 2, 4])", "1");
+        test("rank([1, 2, 3; 2, 4, 6; 1, 1, 1])", "2");
+        test("rank([0, 0; 0, 0])", "0");
+    }
+
+    #[test]
+    fn test_matrix_fn_inv_identity() {
+        test("inv([1, 2; 3, 4])", "[-2, 1; 1.5, -0.5]");
+        test("inv([2, 0; 0, 4])", "[0.5, 0; 0, 0.25]");
+        test("inv([1, 2; 2, 4])", "Err");
+        test("inv([1, 2])", "Err");
+
+        test("identity(1)", "[1]");
+        test("identity(2)", "[1, 0; 0, 1]");
+        test("identity(3) * [1; 2; 3]", "[1; 2; 3]");
+        test("identity(0)", "Err");
+        test("identity(1.5)", "Err");
+    }
+
+    #[test]
+    fn test_matrix_fn_solve() {
+        test("solve([2, 1; 1, 3], [3; 5])", "[0.8; 1.4]");
+        test("solve([1, 1; 1, -1], [10 m; 2 m])", "[6 m; 4 m]");
+        test("solve([1, 2; 2, 4], [1; 2])", "Err");
+        test("solve([1, 2; 3, 4], [1; 2; 3])", "Err");
+        test("solve([1, 2; 3, 4], 2)", "Err");
+    }
+
     #[test]
     fn matrix_unit() {
         test("[2cm,3mm; 4m,5km] in m", "[0.02 m, 0.003 m; 4 m, 5000 m]");
@@ -1619,6 +2660,52 @@ mod tests {
         test("1 + [2, asda]", "1");
     }
 
+    #[test]
+    fn test_comparison_ops() {
+        test("2 < 3", "true");
+        test("2 > 3", "false");
+        test("3 <= 3", "true");
+        test("3 >= 4", "false");
+        test("3 == 3", "true");
+        test("3 != 3", "false");
+        test("1 + 2 == 3", "true");
+        test("2 * 3 > 5", "true");
+        test("2 km > 1500 m", "true");
+        test("100 cm == 1 m", "true");
+        test("2 km > 1500 kg", "Err");
+        test("85% > 80%", "true");
+        test("0.85 > 80%", "true");
+        test("0.5 >= 50%", "true");
+        test("2 > 1 == 3 > 2", "Err");
+    }
+
+    #[test]
+    fn test_logical_ops() {
+        test("2 < 3 and 3 < 4", "true");
+        test("2 < 3 and 3 > 4", "false");
+        test("2 > 3 or 3 < 4", "true");
+        test("not 2 > 3", "true");
+        test("not (2 < 3)", "false");
+        test("2 > 3 or 3 > 4 or 1 == 1", "true");
+        test("2 > 3 and 3 > 4 or 1 == 1", "true");
+        test("not 2 > 3 and 3 > 4", "false");
+        // without boolean operands they are just words
+        test("2 and 3", "2");
+        test("not 2", "2");
+        test("2 > 1 and not_a_var", "true");
+    }
+
+    #[test]
+    fn test_if_fn() {
+        test("if(2 > 1, 10, 20)", "10");
+        test("if(2 < 1, 10, 20)", "20");
+        test("if(85% > 80%, 2, 1)", "2");
+        test("if(2 km > 1500 m, 1 m, 2 m)", "1 m");
+        test("if(1 == 1 and 2 == 3, 1, 2)", "2");
+        test("if(1, 10, 20)", "Err");
+        test("if(1 > 0, 10)", "Err");
+    }
+
     #[test]
     fn test_binary_ops() {
         test("0xFF AND 0b111", "7");
@@ -1678,6 +2765,7 @@ mod tests {
                 CalcResultType::Number(Decimal::from_str("12").unwrap()),
                 0,
             )),
+            user_fn: None,
         });
         test_vars(&vars, "var * 2", "24", 0);
         test_vars(&vars, "var - var", "0", 0);
@@ -1770,6 +2858,31 @@ mod tests {
     #[test]
     fn test_func_sum() {
         test("sum([5, 6, 7])", "18");
+        test("sum(5, 6, 7)", "18");
+        test("sum(1 m, 50 cm)", "1.5 m");
+        test("sum(1 m, 2 kg)", "Err");
+    }
+
+    #[test]
+    fn test_func_aggregates() {
+        test("avg([1, 2, 3])", "2");
+        test("mean(1, 2, 3, 4)", "2.5");
+        test("avg(2 m, 100 cm)", "1.5 m");
+        test("min(3, 1, 2)", "1");
+        test("max([3, 1, 2])", "3");
+        test("max(3 m, 250 cm)", "3 m");
+        test("min(3 m, 250 cm)", "250 cm");
+        test("max(1 m, 2 kg)", "Err");
+        test("median(3, 1, 2)", "2");
+        test("median([4, 1, 3, 2])", "2.5");
+        test("count([1, 2; 3, 4])", "4");
+        test("count(1, [2, 3])", "3");
+        test("prod(2, 3, 4)", "24");
+        test("prod(2 m, 3 m)", "6 m^2");
+        test("variance(2, 4, 4, 4, 5, 5, 7, 9)", "4.5714");
+        test("stddev(1, 2, 3, 4)", "1.291");
+        test("stddev(1 m, 3 m)", "1.4142 m");
+        test("variance(1)", "Err");
     }
 
     #[test]
@@ -1778,6 +2891,29 @@ mod tests {
         test("13 AND NOT(4 - 1)", "12");
     }
 
+    #[test]
+    fn test_big_int_literals_and_bit_ops() {
+        test("0xFFFFFFFFFFFFFFFF", "18446744073709551615");
+        test("0xFFFFFFFFFFFFFFFF AND 0xFF00", "65280");
+        test(
+            "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+            "340282366920938463463374607431768211455",
+        );
+        test(
+            "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF XOR 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF0",
+            "15",
+        );
+        test("1 << 100", "1267650600228229401496703205376");
+        test("(1 << 100) >> 99", "2");
+        test("(1 << 100) OR 1", "1267650600228229401496703205377");
+        test("NOT(1 << 100)", "-1267650600228229401496703205377");
+        test("(1 << 100) + 1 - 1", "1267650600228229401496703205376");
+        test("(1 << 100) / 4", "316912650057057350374175801344");
+        test("(1 << 100) / 3", "Err");
+        test("1 << 257", "Err");
+        test("(1 << 128) > 0xFFFFFFFFFFFFFFFF", "true");
+    }
+
     #[test]
     fn test_func_transpose() {
         test("transpose([5, 6, 7])", "[5; 6; 7]");
@@ -1791,6 +2927,327 @@ mod tests {
         test("pi(1)", "Err");
     }
 
+    #[test]
+    fn test_complex_numbers() {
+        test("3 + 4i", "3 + 4i");
+        test("2j * 3", "6i");
+        test("-2i", "-2i");
+        test("(3 + 4i) * (1 - 2i)", "11 - 2i");
+        test("(3 + 4i) / (1 + 2i)", "2.2 - 0.4i");
+        test("(1 + 1i)^2", "2i");
+        test("1i^2", "-1");
+        test("(1 + 2i) - 2i", "1");
+        test("3 + 4i == 3 + 4i", "true");
+        test("(3 + 4i) / 0", "Err");
+    }
+
+    #[test]
+    fn test_complex_numbers_with_units() {
+        test("(3 + 4i) ohm", "(3 + 4i) ohm");
+        test("(3 + 4i) ohm + 1 ohm", "(4 + 4i) ohm");
+        test("(3 + 4i) kohm in ohm", "(3000 + 4000i) ohm");
+        test("(3 + 4i) ohm + 1 m", "Err");
+    }
+
+    #[test]
+    fn test_complex_fns() {
+        test("re(3 + 4i)", "3");
+        test("im(3 - 4i)", "-4");
+        test("abs(3 + 4i)", "5");
+        test("abs(-3)", "3");
+        test("conj(3 + 4i)", "3 - 4i");
+        test("arg(1i) in deg", "90 deg");
+        test("re(5)", "5");
+        test("im(5)", "0");
+        test("re(1 < 2)", "Err");
+        test("abs(1 + 1i)", "1.4142");
+        // the digits beyond the precision of f64
+        test("(abs(1 + 1i) - 1.414213562373095048) * 1e18", "0.8017");
+        test("(1 + 2i)^0.5", "Err");
+        test("(1 + 2i)^-1", "0.2 - 0.4i");
+    }
+
+    #[test]
+    fn test_exact_fractions() {
+        test("1/3", "0.3333");
+        test("1/3 * 3", "1");
+        test("1/3 * 3 == 1", "true");
+        test("1/3 + 1/6 == 1/2", "true");
+        test("(1/3)^2 * 9 == 1", "true");
+        test("-(1/3) * 3", "-1");
+        test("[1/3, 1/6] * 6", "[2, 1]");
+        test("sqrt(1/4)", "0.5");
+        test("1/3 / 0", "Err");
+        test("(1/3)^0.5", "Err");
+        test("(1/3)^-2", "9");
+        // the numerator doesn't fit into 96 bits
+        test("(1 << 96) * (1/3)", "26409387504754779197847983445");
+        test("-(1 << 96) * (1/3)", "-26409387504754779197847983445");
+    }
+
+    #[test]
+    fn test_dates() {
+        test("2026-10-18", "2026-10-18");
+        test("2026-10-18 + 3 weeks", "2026-11-08");
+        test("3 weeks + 2026-10-18", "2026-11-08");
+        test("2026-10-18 - 1 day", "2026-10-17");
+        test("2024-02-28 + 1 day", "2024-02-29");
+        test("2026-10-18 12:30 + 90 min", "2026-10-18 14:00");
+        test("2026-10-18 + 12 h", "2026-10-18 12:00");
+        test("2026-12-24 - 2026-10-18", "67 day");
+        test("2026-12-24 - 2026-10-18 in h", "1608 h");
+        test("2026-12-24 - 2026-10-18 in days", "67 day");
+        test("2026-12-24 > 2026-10-18", "true");
+        test("weekday(2026-10-18)", "7");
+        test("weekday(2026-10-19)", "1");
+        test("today - today", "0 day");
+        test("now - now", "0 h");
+        test("weekday(today) > 0", "true");
+        test("today + 3 weeks - today in days", "21 day");
+        // not part of an expression
+        test("now we have 5 apples", "5");
+
+        test("2026-10-18 * 2", "Err");
+        test("2026-10-18 + 2 m", "Err");
+        test("weekday(5)", "Err");
+    }
+
+    #[test]
+    fn test_durations() {
+        test("1:30:15", "1.5042 h");
+        test("1:30:15 in s", "5415 s");
+        test("2h 30min", "2.5 h");
+        test("2h 30min in min", "150 min");
+        test("1d 4h", "1.1667 day");
+        test("2 h 30 min", "2.5 h");
+        test("9:15 - 17:40 - 0:45", "7.6667 h");
+        test("9:15 - 17:40", "8.4167 h");
+        test("2:00 - 1:30", "0.5 h");
+        test("1:30 - 0:45", "0.75 h");
+        test("2 * 1:30", "3 h");
+        test("1:30 - 1:30", "0 h");
+    }
+
+    #[test]
+    fn test_radix_conversion() {
+        test("0o755", "493");
+        test("0o755 + 1", "494");
+        test("493 to base 8", "755");
+        test("255 to base 16", "FF");
+        test("255 in base 36", "73");
+        test("0xff + 1 to base 2", "100000000");
+        test("-255 to base 16", "-FF");
+        test("6/2 to base 3", "10");
+
+        test("1.5 to base 2", "Err");
+        test("12 m to base 2", "Err");
+    }
+
+    #[test]
+    fn test_calc_errors() {
+        fn test_err(text: &str, expected: CalcError) {
+            let temp = text.chars().collect::<Vec<char>>();
+            let units = Units::new();
+            let vars = create_vars();
+            let mut tokens = vec![];
+            let arena = Bump::new();
+            let mut shunting_output = crate::shunting_yard::tests::do_shunting_yard(
+                &temp,
+                &units,
+                &mut tokens,
+                &vars,
+                &arena,
+            );
+            let result =
+                crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, &vars, &units);
+            assert_eq!(result.err(), Some(expected), "{}", text);
+        }
+
+        const LENGTH: [i8; 10] = [0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        const TIME: [i8; 10] = [0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        test_err("1 / 0", CalcError::DivisionByZero);
+        test_err("3 m / 0", CalcError::DivisionByZero);
+        test_err("1 m + 1 s", CalcError::IncompatibleUnits(LENGTH, TIME));
+        test_err("2 s in m", CalcError::IncompatibleUnits(TIME, LENGTH));
+        test_err("1 m == 1 s", CalcError::IncompatibleUnits(LENGTH, TIME));
+        test_err("1 m != 1 s", CalcError::IncompatibleUnits(LENGTH, TIME));
+        test_err("2^1000", CalcError::Overflow);
+        test_err("0^-1", CalcError::DivisionByZero);
+        test_err("(2 m)^0.5", CalcError::InvalidOperation);
+        test_err("(1/3)^0.5", CalcError::InvalidOperation);
+        test_err("2^(1 m)", CalcError::IncompatibleUnits(LENGTH, [0; 10]));
+        test_err("ln(2 m)", CalcError::IncompatibleUnits(LENGTH, [0; 10]));
+        test_err("ln(0)", CalcError::InvalidOperation);
+        test_err("sum(1 m, 1 s)", CalcError::IncompatibleUnits(LENGTH, TIME));
+        test_err("round_to(3 m, 0)", CalcError::DivisionByZero);
+        test_err(
+            "sin(1, 2)",
+            CalcError::WrongArgumentCount {
+                min: 1,
+                max: 1,
+                got: 2,
+            },
+        );
+        test_err(
+            "round(1, 2, 3)",
+            CalcError::WrongArgumentCount {
+                min: 1,
+                max: 2,
+                got: 3,
+            },
+        );
+
+        assert_eq!(
+            CalcError::IncompatibleUnits(LENGTH, TIME).to_string(),
+            "incompatible units: length and time"
+        );
+        assert_eq!(
+            CalcError::IncompatibleUnits([0, 2, -1, 0, 0, 0, 0, 0, 0, 0], [0; 10]).to_string(),
+            "incompatible units: length^2 * time^-1 and no unit"
+        );
+    }
+
+    #[test]
+    fn test_fixed_width_ints() {
+        test("255 as u8", "255");
+        test("256 as u8", "0");
+        test("-1 as u8", "255");
+        test("200 as i8", "-56");
+        test("-1 as i64", "-1");
+        test("0xFFFF_FFFF as i32", "-1");
+        test("NOT(5 as u8)", "250");
+        test("NOT(5) as u8", "250");
+        test("(200 as u8) << 1", "144");
+        test("(-128 as i8) >> 1", "-64");
+        test("(0x0F as u8) XOR 0xFFF", "240");
+        test("1 + 2 as u16", "3");
+
+        test("1.5 as u8", "Err");
+        test("12 m as u8", "Err");
+    }
+
+    #[test]
+    fn test_percentage_phrases() {
+        test("20% of 150", "30");
+        test("20% of 150 m", "30 m");
+        test("20% on 150", "180");
+        test("20% off 150", "120");
+        test("30 is what % of 150", "20 %");
+        test("30 is 20% of what", "150");
+        test("% change from 120 to 150", "25 %");
+        test("% change from 150 to 120", "-20 %");
+        test("(10 + 10)% of 150", "30");
+    }
+
+    #[test]
+    fn test_func_trigonometric() {
+        test("sin(30 degree)", "0.5");
+        test("sin(90 deg)", "1");
+        test("cos(60 degree)", "0.5");
+        test("cos(200 grad)", "-1");
+        test("tan(45 degree)", "1");
+        test("sin(pi() / 2)", "1");
+        test("sin(0.5 rad) * 2", "0.9589");
+
+        test("sin(2 m)", "Err");
+        test("sin()", "Err");
+        test("cos(1, 2)", "Err");
+    }
+
+    #[test]
+    fn test_func_inverse_trigonometric() {
+        test("asin(1)", "1.5708 rad");
+        test("asin(0.5) in degree", "30 degree");
+        test("acos(0.5) in deg", "60 deg");
+        test("atan(1) in deg", "45 deg");
+        test("atan2(1, 1) in deg", "45 deg");
+        test("atan2(3 m, 300 cm) in deg", "45 deg");
+
+        test("asin(2)", "Err");
+        test("acos(1 deg)", "Err");
+        test("atan2(1 m, 1 kg)", "Err");
+        test("atan2(1)", "Err");
+    }
+
+    #[test]
+    fn test_func_exp_log() {
+        test("ln(1)", "0");
+        test("exp(1)", "2.7183");
+        test("ln(exp(2))", "2");
+        test("log10(1000)", "3");
+        test("log(8, 2)", "3");
+        test("log(81, 3)", "4");
+
+        test("ln(0)", "Err");
+        test("ln(-1)", "Err");
+        test("ln(2 m)", "Err");
+        test("exp(1 s)", "Err");
+        test("log(8 m, 2)", "Err");
+        test("log(8)", "Err");
+    }
+
+    #[test]
+    fn test_func_roots() {
+        test("sqrt(16)", "4");
+        test("sqrt(2)", "1.4142");
+        test("cbrt(27)", "3");
+        test("cbrt(-8)", "-2");
+        test("root(16, 4)", "2");
+        test("root(-32, 5)", "-2");
+
+        test("sqrt(16 m^2)", "4 m");
+        test("sqrt(16 km^2)", "4 km");
+        test("sqrt(4 m * 1 m)", "2 m");
+        test("cbrt(8 m^3)", "2 m");
+        test("root(16 m^4, 2)", "4 m^2");
+
+        test("sqrt(9 m)", "Err");
+        test("cbrt(8 m^2)", "Err");
+        test("sqrt(-1)", "Err");
+        test("root(16, 0)", "Err");
+        test("root(16, 1.5)", "Err");
+        test("root(16)", "Err");
+    }
+
+    #[test]
+    fn test_func_rounding() {
+        test("round(2.5)", "3");
+        test("round(2.4)", "2");
+        test("round(3.14159, 2)", "3.14");
+        test("round(12 * 1.27, 1)", "15.2");
+        test("round(1.555 km, 2)", "1.56 km");
+        test("floor(3.7)", "3");
+        test("floor(-3.2)", "-4");
+        test("ceil(3.2)", "4");
+        test("ceil(3.21 hour in min)", "193 min");
+        test("ceil(3.21 hour to min)", "193 min");
+        test("trunc(-3.7)", "-3");
+        test("trunc(2.9 m)", "2 m");
+        test("abs(-3)", "3");
+        test("abs(-3 kg)", "3 kg");
+        test("floor([1.5, 2.5; -1.5, 3])", "[1, 2; -2, 3]");
+        test("round([1.25 m, 2.75 m], 1)", "[1.3 m, 2.8 m]");
+
+        test("round(1.5, -1)", "Err");
+        test("round(1.5, 0.5)", "Err");
+        test("floor()", "Err");
+        test("abs(1, 2)", "Err");
+    }
+
+    #[test]
+    fn test_func_round_to() {
+        test("round_to(1.23, 0.05)", "1.25");
+        test("round_to(37 min, 15 min)", "30 min");
+        test("round_to(38 min, 0.25 hour)", "45 min");
+        test("round_to(38 min, 15)", "45 min");
+        test("round_to(1234, 100)", "1200");
+        test("round_to([1.12, 1.18], 0.1)", "[1.1, 1.2]");
+
+        test("round_to(37 min, 1 m)", "Err");
+        test("round_to(37, 0)", "Err");
+        test("round_to(37)", "Err");
+    }
+
     #[test]
     fn test_single_brackets() {
         test("[", " ");
@@ -0,0 +1,127 @@
+use rust_decimal::prelude::*;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Local time in seconds since 1970-01-01 00:00, the timezone is not stored
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DateTime {
+    pub secs: i64,
+    /// false for dates like 2026-10-18 or today, they are rendered without the time part
+    pub has_time: bool,
+}
+
+impl DateTime {
+    pub fn from_ymd_hms(
+        year: i64,
+        month: u32,
+        day: u32,
+        hour: u32,
+        min: u32,
+        sec: u32,
+        has_time: bool,
+    ) -> Option<DateTime> {
+        if month < 1 || month > 12 || day < 1 || hour > 23 || min > 59 || sec > 59 {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        if civil_from_days(days) != (year, month, day) {
+            // e.g. 2026-02-30
+            return None;
+        }
+        let secs = days
+            .checked_mul(SECONDS_PER_DAY)?
+            .checked_add(i64::from(hour * 3600 + min * 60 + sec))?;
+        Some(DateTime { secs, has_time })
+    }
+
+    pub fn from_secs(secs: i64, has_time: bool) -> DateTime {
+        DateTime {
+            secs: if has_time {
+                secs
+            } else {
+                secs - secs.rem_euclid(SECONDS_PER_DAY)
+            },
+            has_time,
+        }
+    }
+
+    pub fn ymd(&self) -> (i64, u32, u32) {
+        civil_from_days(self.secs.div_euclid(SECONDS_PER_DAY))
+    }
+
+    pub fn hms(&self) -> (u32, u32, u32) {
+        let secs = self.secs.rem_euclid(SECONDS_PER_DAY) as u32;
+        (secs / 3600, secs / 60 % 60, secs % 60)
+    }
+
+    /// 1 is Monday, 7 is Sunday
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday
+        ((self.secs.div_euclid(SECONDS_PER_DAY) + 3).rem_euclid(7) + 1) as u32
+    }
+
+    /// the date gets a time part only if the duration is not a multiple of whole days
+    pub fn checked_add_secs(&self, secs: &Decimal) -> Option<DateTime> {
+        let secs = secs.round().to_i64()?;
+        Some(DateTime {
+            secs: self.secs.checked_add(secs)?,
+            has_time: self.has_time || secs % SECONDS_PER_DAY != 0,
+        })
+    }
+}
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+const NO_CURRENT_TIME: i64 = i64::MIN;
+static CURRENT_TIME: AtomicI64 = AtomicI64::new(NO_CURRENT_TIME);
+
+/// There is no clock in wasm, so the frontend has to provide it for `today` and `now`
+pub fn set_current_time(local_secs: i64) {
+    CURRENT_TIME.store(local_secs, Ordering::Relaxed);
+}
+
+pub fn current_time() -> Option<i64> {
+    let secs = CURRENT_TIME.load(Ordering::Relaxed);
+    if secs != NO_CURRENT_TIME {
+        Some(secs)
+    } else {
+        system_time()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn system_time() -> Option<i64> {
+    use std::convert::TryFrom;
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    i64::try_from(since_epoch.as_secs()).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn system_time() -> Option<i64> {
+    None
+}
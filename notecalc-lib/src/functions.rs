@@ -1,6 +1,19 @@
-use crate::calc::{add_op, CalcResult, CalcResultType};
-use crate::token_parser::Token;
+use crate::calc::{
+    add_op, compare_values, complex_result, dec, dimensions_of, divide_op, evaluate_tokens,
+    multiply_op, sub_op, to_complex, CalcError, CalcResult, CalcResultType,
+};
+use crate::matrix::MatrixData;
+use crate::shunting_yard::ShuntingYard;
+use crate::token_parser::{Token, TokenParser};
+use crate::units::consts::{
+    UnitDimensionExponent, BASE_UNIT_DIMENSION_COUNT, EMPTY_UNIT_DIMENSIONS,
+};
+use crate::units::units::{UnitOutput, Units};
+use crate::{is_aggregate_variable, Variable, Variables, MAX_LINE_COUNT};
+use bumpalo::Bump;
 use rust_decimal::prelude::*;
+use rust_decimal::RoundingStrategy;
+use std::cmp::Ordering;
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -9,10 +22,51 @@ use strum_macros::EnumIter;
 pub enum FnType {
     Sin,
     Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Ln,
+    Log10,
+    Log,
+    Exp,
+    Sqrt,
+    Cbrt,
+    Root,
+    Round,
+    RoundTo,
+    Floor,
+    Ceil,
+    Trunc,
+    Abs,
+    Re,
+    Im,
+    Arg,
+    Conj,
+    Weekday,
     Nth,
     Sum,
+    Avg,
+    Mean,
+    Min,
+    Max,
+    Median,
+    Count,
+    Prod,
+    Variance,
+    Stddev,
     Transpose,
+    Det,
+    Inv,
+    Identity,
+    Trace,
+    Rank,
+    Solve,
     Pi,
+    If,
+    /// defined in the note, e.g. `area(w, h) = w * h`, the value is the index of the defining line
+    UserDefined(usize),
 }
 
 impl FnType {
@@ -31,10 +85,104 @@ impl FnType {
         match self {
             FnType::Sin => &['s', 'i', 'n'],
             FnType::Cos => &['c', 'o', 's'],
+            FnType::Tan => &['t', 'a', 'n'],
+            FnType::Asin => &['a', 's', 'i', 'n'],
+            FnType::Acos => &['a', 'c', 'o', 's'],
+            FnType::Atan => &['a', 't', 'a', 'n'],
+            FnType::Atan2 => &['a', 't', 'a', 'n', '2'],
+            FnType::Ln => &['l', 'n'],
+            FnType::Log10 => &['l', 'o', 'g', '1', '0'],
+            FnType::Log => &['l', 'o', 'g'],
+            FnType::Exp => &['e', 'x', 'p'],
+            FnType::Sqrt => &['s', 'q', 'r', 't'],
+            FnType::Cbrt => &['c', 'b', 'r', 't'],
+            FnType::Root => &['r', 'o', 'o', 't'],
+            FnType::Round => &['r', 'o', 'u', 'n', 'd'],
+            FnType::RoundTo => &['r', 'o', 'u', 'n', 'd', '_', 't', 'o'],
+            FnType::Floor => &['f', 'l', 'o', 'o', 'r'],
+            FnType::Ceil => &['c', 'e', 'i', 'l'],
+            FnType::Trunc => &['t', 'r', 'u', 'n', 'c'],
+            FnType::Abs => &['a', 'b', 's'],
+            FnType::Re => &['r', 'e'],
+            FnType::Im => &['i', 'm'],
+            FnType::Arg => &['a', 'r', 'g'],
+            FnType::Conj => &['c', 'o', 'n', 'j'],
+            FnType::Weekday => &['w', 'e', 'e', 'k', 'd', 'a', 'y'],
             FnType::Nth => &['n', 't', 'h'],
             FnType::Sum => &['s', 'u', 'm'],
+            FnType::Avg => &['a', 'v', 'g'],
+            FnType::Mean => &['m', 'e', 'a', 'n'],
+            FnType::Min => &['m', 'i', 'n'],
+            FnType::Max => &['m', 'a', 'x'],
+            FnType::Median => &['m', 'e', 'd', 'i', 'a', 'n'],
+            FnType::Count => &['c', 'o', 'u', 'n', 't'],
+            FnType::Prod => &['p', 'r', 'o', 'd'],
+            FnType::Variance => &['v', 'a', 'r', 'i', 'a', 'n', 'c', 'e'],
+            FnType::Stddev => &['s', 't', 'd', 'd', 'e', 'v'],
             FnType::Transpose => &['t', 'r', 'a', 'n', 's', 'p', 'o', 's', 'e'],
+            FnType::Det => &['d', 'e', 't'],
+            FnType::Inv => &['i', 'n', 'v'],
+            FnType::Identity => &['i', 'd', 'e', 'n', 't', 'i', 't', 'y'],
+            FnType::Trace => &['t', 'r', 'a', 'c', 'e'],
+            FnType::Rank => &['r', 'a', 'n', 'k'],
+            FnType::Solve => &['s', 'o', 'l', 'v', 'e'],
             FnType::Pi => &['p', 'i'],
+            FnType::If => &['i', 'f'],
+            // its name is stored in the variable of the defining line
+            FnType::UserDefined(_) => &[],
+        }
+    }
+
+    /// The accepted minimum and maximum number of arguments
+    pub fn arg_count_range(&self, vars: &Variables) -> (usize, usize) {
+        match self {
+            FnType::Pi => (0, 0),
+            FnType::Round => (1, 2),
+            FnType::Atan2 | FnType::Log | FnType::Root | FnType::RoundTo | FnType::Solve => (2, 2),
+            FnType::If => (3, 3),
+            FnType::Nth => (2, usize::MAX),
+            FnType::Sum
+            | FnType::Avg
+            | FnType::Mean
+            | FnType::Min
+            | FnType::Max
+            | FnType::Median
+            | FnType::Count
+            | FnType::Prod
+            | FnType::Variance
+            | FnType::Stddev
+            | FnType::Transpose => (1, usize::MAX),
+            FnType::UserDefined(var_index) => vars
+                .get(*var_index)
+                .and_then(|it| it.as_ref())
+                .and_then(|it| it.user_fn.as_ref())
+                .map(|user_fn| (user_fn.params.len(), user_fn.params.len()))
+                .unwrap_or((0, usize::MAX)),
+            FnType::Sin
+            | FnType::Cos
+            | FnType::Tan
+            | FnType::Asin
+            | FnType::Acos
+            | FnType::Atan
+            | FnType::Ln
+            | FnType::Log10
+            | FnType::Exp
+            | FnType::Sqrt
+            | FnType::Cbrt
+            | FnType::Floor
+            | FnType::Ceil
+            | FnType::Trunc
+            | FnType::Abs
+            | FnType::Re
+            | FnType::Im
+            | FnType::Arg
+            | FnType::Conj
+            | FnType::Weekday
+            | FnType::Det
+            | FnType::Inv
+            | FnType::Identity
+            | FnType::Trace
+            | FnType::Rank => (1, 1),
         }
     }
 
@@ -45,28 +193,285 @@ impl FnType {
         stack: &mut Vec<CalcResult>,
         fn_token_index: usize,
         tokens: &mut [Token<'text_ptr>],
-    ) -> bool {
+        units: &Units,
+        vars: &Variables,
+    ) -> Result<(), CalcError> {
         match self {
             FnType::Nth => fn_nth(arg_count, stack, tokens, fn_token_index),
-            FnType::Sum => fn_sum(arg_count, stack),
+            FnType::Sum => fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_sum),
+            FnType::Avg | FnType::Mean => {
+                fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_avg)
+            }
+            FnType::Min => fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_min),
+            FnType::Max => fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_max),
+            FnType::Median => {
+                fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_median)
+            }
+            FnType::Count => {
+                fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_count)
+            }
+            FnType::Prod => fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_prod),
+            FnType::Variance => fn_aggregate(
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                agg_variance,
+            ),
+            FnType::Stddev => {
+                fn_aggregate(arg_count, stack, tokens, fn_token_index, units, agg_stddev)
+            }
             FnType::Transpose => fn_transpose(arg_count, stack),
+            FnType::Det => fn_matrix(arg_count, stack, tokens, fn_token_index, |mat| {
+                mat.det().map(|it| it.typ)
+            }),
+            FnType::Inv => fn_matrix(arg_count, stack, tokens, fn_token_index, |mat| {
+                mat.inverse().map(CalcResultType::Matrix)
+            }),
+            FnType::Trace => fn_matrix(arg_count, stack, tokens, fn_token_index, |mat| {
+                mat.trace().map(|it| it.typ)
+            }),
+            FnType::Rank => fn_matrix(arg_count, stack, tokens, fn_token_index, |mat| {
+                mat.rank().map(|it| CalcResultType::Number(dec(it as i64)))
+            }),
+            FnType::Identity => fn_identity(arg_count, stack, tokens, fn_token_index),
+            FnType::Solve => fn_solve(arg_count, stack, tokens, fn_token_index),
             FnType::Pi => fn_pi(arg_count, stack, fn_token_index),
-            FnType::Sin => true,
-            FnType::Cos => true,
+            FnType::If => fn_if(arg_count, stack, tokens, fn_token_index),
+            FnType::Weekday => fn_weekday(arg_count, stack, tokens, fn_token_index),
+            FnType::UserDefined(var_index) => fn_user_defined(
+                *var_index,
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                vars,
+            ),
+            FnType::Sin => fn_trig(arg_count, stack, tokens, fn_token_index, units, f64::sin),
+            FnType::Cos => fn_trig(arg_count, stack, tokens, fn_token_index, units, f64::cos),
+            FnType::Tan => fn_trig(arg_count, stack, tokens, fn_token_index, units, f64::tan),
+            FnType::Asin => {
+                fn_inverse_trig(arg_count, stack, tokens, fn_token_index, units, f64::asin)
+            }
+            FnType::Acos => {
+                fn_inverse_trig(arg_count, stack, tokens, fn_token_index, units, f64::acos)
+            }
+            FnType::Atan => {
+                fn_inverse_trig(arg_count, stack, tokens, fn_token_index, units, f64::atan)
+            }
+            FnType::Atan2 => fn_atan2(arg_count, stack, tokens, fn_token_index, units),
+            FnType::Ln => fn_dimensionless(arg_count, stack, tokens, fn_token_index, f64::ln),
+            FnType::Log10 => fn_dimensionless(arg_count, stack, tokens, fn_token_index, f64::log10),
+            FnType::Exp => fn_dimensionless(arg_count, stack, tokens, fn_token_index, f64::exp),
+            FnType::Log => fn_log(arg_count, stack, tokens, fn_token_index),
+            FnType::Sqrt => fn_root(arg_count, stack, tokens, fn_token_index, units, Some(2)),
+            FnType::Cbrt => fn_root(arg_count, stack, tokens, fn_token_index, units, Some(3)),
+            FnType::Root => fn_root(arg_count, stack, tokens, fn_token_index, units, None),
+            FnType::Round => fn_round(arg_count, stack, tokens, fn_token_index),
+            FnType::RoundTo => fn_round_to(arg_count, stack, tokens, fn_token_index),
+            FnType::Floor => {
+                fn_in_own_unit(arg_count, stack, tokens, fn_token_index, |it| it.floor())
+            }
+            FnType::Ceil => {
+                fn_in_own_unit(arg_count, stack, tokens, fn_token_index, |it| it.ceil())
+            }
+            FnType::Trunc => {
+                fn_in_own_unit(arg_count, stack, tokens, fn_token_index, |it| it.trunc())
+            }
+            FnType::Abs => {
+                if let Some(CalcResultType::Complex(..)) = stack.last().map(|it| &it.typ) {
+                    fn_complex(arg_count, stack, tokens, fn_token_index, units, complex_abs)
+                } else {
+                    fn_in_own_unit(arg_count, stack, tokens, fn_token_index, |it| it.abs())
+                }
+            }
+            FnType::Re => fn_complex(
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                |re, _, unit, _| Some(complex_result(*re, Decimal::zero(), unit.clone())),
+            ),
+            FnType::Im => fn_complex(
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                |_, im, unit, _| Some(complex_result(*im, Decimal::zero(), unit.clone())),
+            ),
+            FnType::Arg => fn_complex(
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                |re, im, _, units| {
+                    let angle = im.to_f64()?.atan2(re.to_f64()?);
+                    Some(CalcResultType::Quantity(
+                        Decimal::from_f64(angle)?,
+                        rad_unit(units),
+                    ))
+                },
+            ),
+            FnType::Conj => fn_complex(
+                arg_count,
+                stack,
+                tokens,
+                fn_token_index,
+                units,
+                |re, im, unit, _| Some(complex_result(*re, -*im, unit.clone())),
+            ),
         }
     }
 }
 
-fn fn_pi(arg_count: usize, stack: &mut Vec<CalcResult>, token_index: usize) -> bool {
+fn rad_unit(units: &Units) -> UnitOutput {
+    units.parse(&['r', 'a', 'd']).0
+}
+
+fn rad_quantity(rad: f64, units: &Units, token_index: usize) -> Option<CalcResult> {
+    let num = Decimal::from_f64(rad)?;
+    Some(CalcResult::new(
+        CalcResultType::Quantity(num, rad_unit(units)),
+        token_index,
+    ))
+}
+
+/// IncompatibleUnits if the parameter has other dimensions than the expected ones,
+/// e.g. ln(2 m), otherwise the value itself is invalid for the function, e.g. ln(0)
+fn param_error(
+    param: &CalcResult,
+    expected: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+) -> CalcError {
+    match dimensions_of(param) {
+        Some(dimensions) if dimensions != expected => {
+            CalcError::IncompatibleUnits(dimensions, expected)
+        }
+        _ => CalcError::InvalidOperation,
+    }
+}
+
+/// the parameter is an angle, plain numbers are treated as radians
+fn fn_trig<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    f: fn(f64) -> f64,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    let rad = match &param.typ {
+        CalcResultType::Number(num) => num.to_f64(),
+        // quantities are stored in their base unit, which is radian for angles
+        CalcResultType::Quantity(num, unit) if *unit == rad_unit(units) => num.to_f64(),
+        _ => None,
+    };
+    if let Some(result) = rad.and_then(|rad| Decimal::from_f64(f(rad))) {
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(
+            CalcResultType::Number(result),
+            fn_token_index,
+        ));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(param_error(param, rad_unit(units).dimensions))
+    }
+}
+
+fn fn_inverse_trig<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    f: fn(f64) -> f64,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    let result = match &param.typ {
+        // NaN (e.g. asin(2)) can't be converted back, so it is an error
+        CalcResultType::Number(num) => num
+            .to_f64()
+            .and_then(|it| rad_quantity(f(it), units, fn_token_index)),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 1);
+        stack.push(result);
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(param_error(param, EMPTY_UNIT_DIMENSIONS))
+    }
+}
+
+fn fn_atan2<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+) -> Result<(), CalcError> {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let y_token = &stack[stack.len() - 2];
+    let x_token = &stack[stack.len() - 1];
+    let (y, x) = match (&y_token.typ, &x_token.typ) {
+        (CalcResultType::Number(y), CalcResultType::Number(x)) => (y.to_f64(), x.to_f64()),
+        // e.g. atan2(3 m, 400 cm), both are normalized into their base unit
+        (CalcResultType::Quantity(y, y_unit), CalcResultType::Quantity(x, x_unit))
+            if y_unit == x_unit =>
+        {
+            (y.to_f64(), x.to_f64())
+        }
+        _ => (None, None),
+    };
+    if let Some(result) = y
+        .zip(x)
+        .and_then(|(y, x)| rad_quantity(y.atan2(x), units, fn_token_index))
+    {
+        stack.truncate(stack.len() - 2);
+        stack.push(result);
+        Ok(())
+    } else {
+        y_token.set_token_error_flag(tokens);
+        x_token.set_token_error_flag(tokens);
+        Err(match dimensions_of(x_token) {
+            Some(x_dimensions) => param_error(y_token, x_dimensions),
+            None => CalcError::InvalidOperation,
+        })
+    }
+}
+
+fn fn_pi(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    token_index: usize,
+) -> Result<(), CalcError> {
     if arg_count != 0 {
-        return false;
+        return Err(CalcError::InvalidOperation);
     }
 
     stack.push(CalcResult::new(CalcResultType::Number(
         Decimal::from_str("3.1415926535897932384626433832795028841971693993751058209749445923078164062862089986280348253421170679821480865132823066470938446095505822317253594081284811174502841027019385211055596446229489549303819644288109756659334461284756482337867831652712019091456485669234603486104543266482133936072602491412737245870066063155881748815209209628292540917153643678925903600113305305488204665213841469519415116094330572703657595919530921861173819326117931051185480744623799627495673518857527248912279381830119491298336733624406566430860213949463952247371907021798609437027705392171762931767523846748184676694051320005681271452635608277857713427577896091736371787214684409012249534301465495853710507922796892589235420199561121290219608640344181598136297747713099605187072113499999983729780499510597317328160963185950244594553469083026425223082533446850352619311881710100031378387528865875332083814206171776691473035982534904287554687311595628638823537875937519577818577805321712268066130019278766111959092164201989").unwrap()
     ), token_index));
 
-    true
+    Ok(())
 }
 
 fn fn_nth<'text_ptr>(
@@ -74,10 +479,10 @@ fn fn_nth<'text_ptr>(
     stack: &mut Vec<CalcResult>,
     tokens: &mut [Token<'text_ptr>],
     fn_token_index: usize,
-) -> bool {
+) -> Result<(), CalcError> {
     if arg_count < 2 || stack.len() < 2 {
         Token::set_token_error_flag_by_index(fn_token_index, tokens);
-        false
+        Err(CalcError::InvalidOperation)
     } else {
         let index_token = &stack[stack.len() - 1];
         let mat_token = &stack[stack.len() - 2];
@@ -86,62 +491,38 @@ fn fn_nth<'text_ptr>(
                 if let Some(index) = n.to_u32() {
                     if mat.col_count < (index + 1) as usize {
                         index_token.set_token_error_flag(tokens);
-                        false
+                        Err(CalcError::InvalidOperation)
                     } else {
                         let result = mat.cell(0, index as usize).clone();
                         stack.truncate(stack.len() - 2);
                         stack.push(result);
-                        true
+                        Ok(())
                     }
                 } else {
                     index_token.set_token_error_flag(tokens);
-                    false
+                    Err(CalcError::InvalidOperation)
                 }
             }
             (CalcResultType::Number(_), _) => {
                 mat_token.set_token_error_flag(tokens);
-                false
+                Err(CalcError::InvalidOperation)
             }
             (_, CalcResultType::Matrix(_)) => {
                 index_token.set_token_error_flag(tokens);
-                false
+                Err(CalcError::InvalidOperation)
             }
             _ => {
                 index_token.set_token_error_flag(tokens);
                 mat_token.set_token_error_flag(tokens);
-                false
-            }
-        }
-    }
-}
-
-fn fn_sum(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
-    if arg_count < 1 {
-        false
-    } else {
-        let param = &stack[stack.len() - 1];
-        match &param.typ {
-            CalcResultType::Matrix(mat) => {
-                let mut sum = mat.cells[0].clone();
-                for cell in mat.cells.iter().skip(1) {
-                    if let Some(result) = add_op(&sum, cell) {
-                        sum = result;
-                    } else {
-                        return false;
-                    }
-                }
-                stack.truncate(stack.len() - 1);
-                stack.push(sum);
-                true
+                Err(CalcError::InvalidOperation)
             }
-            _ => false,
         }
     }
 }
 
-fn fn_transpose(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
+fn fn_transpose(arg_count: usize, stack: &mut Vec<CalcResult>) -> Result<(), CalcError> {
     if arg_count < 1 {
-        false
+        Err(CalcError::InvalidOperation)
     } else {
         let param = &stack[stack.len() - 1];
         let index_into_tokens = param.get_index_into_tokens();
@@ -154,9 +535,705 @@ fn fn_transpose(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
         } {
             stack.truncate(stack.len() - 1);
             stack.push(CalcResult::new(transposed, index_into_tokens));
-            true
+            Ok(())
         } else {
-            false
+            Err(CalcError::InvalidOperation)
+        }
+    }
+}
+
+/// 1 is Monday, 7 is Sunday
+fn fn_weekday<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    if let CalcResultType::Date(date) = &param.typ {
+        let weekday = Decimal::from(date.weekday());
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(
+            CalcResultType::Number(weekday),
+            fn_token_index,
+        ));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
+    }
+}
+
+/// e.g. ln, exp, they accept only dimensionless numbers
+fn fn_dimensionless<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    f: fn(f64) -> f64,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    let result = match &param.typ {
+        // infinity and NaN (e.g. ln(0)) can't be converted back, so they are errors
+        CalcResultType::Number(num) => num.to_f64().and_then(|it| Decimal::from_f64(f(it))),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(
+            CalcResultType::Number(result),
+            fn_token_index,
+        ));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(param_error(param, EMPTY_UNIT_DIMENSIONS))
+    }
+}
+
+fn fn_log<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let num_token = &stack[stack.len() - 2];
+    let base_token = &stack[stack.len() - 1];
+    let result = match (&num_token.typ, &base_token.typ) {
+        (CalcResultType::Number(num), CalcResultType::Number(base)) => num
+            .to_f64()
+            .zip(base.to_f64())
+            .and_then(|(num, base)| Decimal::from_f64(num.log(base))),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 2);
+        stack.push(CalcResult::new(
+            CalcResultType::Number(result),
+            fn_token_index,
+        ));
+        Ok(())
+    } else {
+        num_token.set_token_error_flag(tokens);
+        base_token.set_token_error_flag(tokens);
+        Err(match param_error(num_token, EMPTY_UNIT_DIMENSIONS) {
+            CalcError::InvalidOperation => param_error(base_token, EMPTY_UNIT_DIMENSIONS),
+            err => err,
+        })
+    }
+}
+
+fn nth_root(num: f64, n: i64) -> f64 {
+    return if n == 2 {
+        num.sqrt()
+    } else if n == 3 {
+        num.cbrt()
+    } else if num < 0.0 && n % 2 == 1 {
+        -(-num).powf(1.0 / n as f64)
+    } else {
+        num.powf(1.0 / n as f64)
+    };
+}
+
+fn root_of(typ: &CalcResultType, n: i64, units: &Units) -> Option<CalcResultType> {
+    return match typ {
+        CalcResultType::Number(num) => num
+            .to_f64()
+            .and_then(|num| Decimal::from_f64(nth_root(num, n)))
+            .map(CalcResultType::Number),
+        CalcResultType::Quantity(num, unit) => unit.root(n, units).and_then(|unit| {
+            num.to_f64()
+                .and_then(|num| Decimal::from_f64(nth_root(num, n)))
+                .map(|num| CalcResultType::Quantity(num, unit))
+        }),
+        _ => None,
+    };
+}
+
+/// if `n` is None, it is the second parameter of the function (e.g. root(27, 3))
+fn fn_root<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    n: Option<i64>,
+) -> Result<(), CalcError> {
+    let expected_arg_count = if n.is_some() { 1 } else { 2 };
+    if arg_count != expected_arg_count || stack.len() < expected_arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let n = if let Some(n) = n {
+        n
+    } else {
+        let n_token = &stack[stack.len() - 1];
+        match &n_token.typ {
+            CalcResultType::Number(n)
+                if n.trunc() == *n && n.is_sign_positive() && !n.is_zero() =>
+            {
+                if let Some(n) = n.to_i64() {
+                    n
+                } else {
+                    n_token.set_token_error_flag(tokens);
+                    return Err(CalcError::InvalidOperation);
+                }
+            }
+            _ => {
+                n_token.set_token_error_flag(tokens);
+                return Err(CalcError::InvalidOperation);
+            }
+        }
+    };
+    let param = &stack[stack.len() - expected_arg_count];
+    if let Some(result) = root_of(&param.typ, n, units) {
+        stack.truncate(stack.len() - expected_arg_count);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
+    }
+}
+
+/// Applies `f` on the number as it is displayed, e.g. on 3.2 for "3.2 km" and not on
+/// its normalized 3200 m form. Matrices are processed cell by cell.
+fn map_in_own_unit(
+    typ: &CalcResultType,
+    f: &dyn Fn(&Decimal) -> Option<Decimal>,
+) -> Option<CalcResultType> {
+    return match typ {
+        CalcResultType::Number(num) => f(num).map(CalcResultType::Number),
+        CalcResultType::Percentage(num) => f(num).map(CalcResultType::Percentage),
+        CalcResultType::Quantity(num, unit) => {
+            let num_in_unit = unit.from_base_to_this_unit(num)?;
+            let result = unit.normalize(&f(&num_in_unit)?)?;
+            Some(CalcResultType::Quantity(result, unit.clone()))
+        }
+        CalcResultType::Matrix(mat) => {
+            let cells = mat
+                .cells
+                .iter()
+                .map(|cell| {
+                    map_in_own_unit(&cell.typ, f)
+                        .map(|typ| CalcResult::new(typ, cell.get_index_into_tokens()))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(CalcResultType::Matrix(MatrixData::new(
+                cells,
+                mat.row_count,
+                mat.col_count,
+            )))
         }
+        CalcResultType::Unit(_)
+        | CalcResultType::Boolean(_)
+        | CalcResultType::BigInt(_)
+        | CalcResultType::Complex(..)
+        | CalcResultType::Rational(..)
+        | CalcResultType::Date(..) => None,
+    };
+}
+
+/// Real values are accepted as well, e.g. re(5) is 5
+fn fn_complex<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    f: fn(&Decimal, &Decimal, &UnitOutput, &Units) -> Option<CalcResultType>,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    if let Some(result) =
+        to_complex(&param.typ).and_then(|(re, im, unit)| f(&re, &im, &unit, units))
+    {
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
     }
 }
+
+fn complex_abs(
+    re: &Decimal,
+    im: &Decimal,
+    unit: &UnitOutput,
+    _units: &Units,
+) -> Option<CalcResultType> {
+    let abs = crate::calc::complex_abs(re, im)?;
+    Some(complex_result(abs, Decimal::zero(), unit.clone()))
+}
+
+fn fn_in_own_unit<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    f: fn(&Decimal) -> Decimal,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    if let Some(result) = map_in_own_unit(&param.typ, &|it| Some(f(it))) {
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
+    }
+}
+
+/// round(x) or round(x, digits)
+fn fn_round<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count < 1 || arg_count > 2 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let digits = if arg_count == 2 {
+        let digits_token = &stack[stack.len() - 1];
+        match &digits_token.typ {
+            CalcResultType::Number(n) if n.trunc() == *n && !n.is_sign_negative() => {
+                if let Some(n) = n.to_u32() {
+                    n
+                } else {
+                    digits_token.set_token_error_flag(tokens);
+                    return Err(CalcError::InvalidOperation);
+                }
+            }
+            _ => {
+                digits_token.set_token_error_flag(tokens);
+                return Err(CalcError::InvalidOperation);
+            }
+        }
+    } else {
+        0
+    };
+    let param = &stack[stack.len() - arg_count];
+    if let Some(result) = map_in_own_unit(&param.typ, &|it| {
+        Some(it.round_dp_with_strategy(digits, RoundingStrategy::RoundHalfUp))
+    }) {
+        stack.truncate(stack.len() - arg_count);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
+    }
+}
+
+fn round_to_step(num: &Decimal, step: &Decimal) -> Option<Decimal> {
+    let count = num
+        .checked_div(step)?
+        .round_dp_with_strategy(0, RoundingStrategy::RoundHalfUp);
+    return count.checked_mul(step);
+}
+
+/// round_to(x, step), e.g. round_to(37 min, 15 min) or round_to(1.23, 0.05)
+fn fn_round_to<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 2];
+    let step_token = &stack[stack.len() - 1];
+    let step_is_zero = match &step_token.typ {
+        CalcResultType::Number(step) | CalcResultType::Quantity(step, _) => step.is_zero(),
+        _ => false,
+    };
+    if step_is_zero {
+        step_token.set_token_error_flag(tokens);
+        return Err(CalcError::DivisionByZero);
+    }
+    let result = match (&param.typ, &step_token.typ) {
+        // the step is understood in the unit of the value
+        (typ, CalcResultType::Number(step)) => map_in_own_unit(typ, &|it| round_to_step(it, step)),
+        (CalcResultType::Quantity(num, unit), CalcResultType::Quantity(step, step_unit))
+            if unit == step_unit =>
+        {
+            // both are stored in base unit
+            round_to_step(num, step).map(|it| CalcResultType::Quantity(it, unit.clone()))
+        }
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 2);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        step_token.set_token_error_flag(tokens);
+        Err(match (dimensions_of(param), &step_token.typ) {
+            (Some(dimensions), CalcResultType::Quantity(_, step_unit))
+                if dimensions != step_unit.dimensions =>
+            {
+                CalcError::IncompatibleUnits(dimensions, step_unit.dimensions)
+            }
+            _ => CalcError::InvalidOperation,
+        })
+    }
+}
+
+/// Aggregate functions accept either a matrix (e.g. avg([1, 2, 3]))
+/// or a list of arguments (e.g. avg(1, 2, 3)), matrices in the list are flattened.
+fn fn_aggregate<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    f: fn(&mut [CalcResult], &Units) -> Option<CalcResultType>,
+) -> Result<(), CalcError> {
+    if arg_count < 1 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let mut values = Vec::with_capacity(arg_count);
+    for arg in &stack[stack.len() - arg_count..] {
+        match &arg.typ {
+            CalcResultType::Matrix(mat) => values.extend(mat.cells.iter().cloned()),
+            _ => values.push(arg.clone()),
+        }
+    }
+    // e.g. sum(1 m, 1 s)
+    let dimension_error = values.first().and_then(dimensions_of).and_then(|first| {
+        values
+            .iter()
+            .filter_map(dimensions_of)
+            .find(|it| *it != first)
+            .map(|other| CalcError::IncompatibleUnits(first, other))
+    });
+    if let Some(result) = Some(values)
+        .filter(|it| !it.is_empty())
+        .and_then(|mut values| f(&mut values, units))
+    {
+        stack.truncate(stack.len() - arg_count);
+        stack.push(CalcResult::new(result, fn_token_index));
+        Ok(())
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        Err(dimension_error.unwrap_or(CalcError::InvalidOperation))
+    }
+}
+
+fn sum_of(values: &[CalcResult]) -> Option<CalcResult> {
+    let mut sum = values[0].clone();
+    for value in values.iter().skip(1) {
+        sum = add_op(&sum, value)?;
+    }
+    return Some(sum);
+}
+
+fn avg_of(values: &[CalcResult]) -> Option<CalcResult> {
+    let count = CalcResult::new(CalcResultType::Number(dec(values.len() as i64)), 0);
+    return divide_op(&sum_of(values)?, &count);
+}
+
+/// the values must be comparable with each other, e.g. 3 m and 250 cm
+fn min_max_of(values: &[CalcResult], wanted: Ordering) -> Option<CalcResultType> {
+    let mut result = &values[0];
+    for value in values.iter() {
+        if compare_values(value, result)? == wanted {
+            result = value;
+        }
+    }
+    return Some(result.typ.clone());
+}
+
+fn agg_sum(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    sum_of(values).map(|it| it.typ)
+}
+
+fn agg_avg(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    avg_of(values).map(|it| it.typ)
+}
+
+fn agg_min(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    min_max_of(values, Ordering::Less)
+}
+
+fn agg_max(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    min_max_of(values, Ordering::Greater)
+}
+
+fn agg_median(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    for value in values.iter() {
+        compare_values(value, &values[0])?;
+    }
+    values.sort_by(|a, b| compare_values(a, b).unwrap_or(Ordering::Equal));
+    let mid = values.len() / 2;
+    return if values.len() % 2 == 0 {
+        avg_of(&values[mid - 1..=mid]).map(|it| it.typ)
+    } else {
+        Some(values[mid].typ.clone())
+    };
+}
+
+fn agg_count(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    Some(CalcResultType::Number(dec(values.len() as i64)))
+}
+
+fn agg_prod(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    let mut prod = values[0].clone();
+    for value in values.iter().skip(1) {
+        prod = multiply_op(&prod, value)?;
+    }
+    return Some(prod.typ);
+}
+
+/// sample variance (divided by n-1), like in spreadsheets
+fn agg_variance(values: &mut [CalcResult], _units: &Units) -> Option<CalcResultType> {
+    if values.len() < 2 {
+        return None;
+    }
+    let avg = avg_of(values)?;
+    let mut sum_of_squares: Option<CalcResult> = None;
+    for value in values.iter() {
+        let diff = sub_op(value, &avg)?;
+        let square = multiply_op(&diff, &diff)?;
+        sum_of_squares = Some(if let Some(sum) = sum_of_squares {
+            add_op(&sum, &square)?
+        } else {
+            square
+        });
+    }
+    let n_minus_1 = CalcResult::new(CalcResultType::Number(dec(values.len() as i64 - 1)), 0);
+    return divide_op(&sum_of_squares?, &n_minus_1).map(|it| it.typ);
+}
+
+fn agg_stddev(values: &mut [CalcResult], units: &Units) -> Option<CalcResultType> {
+    let variance = agg_variance(values, units)?;
+    return root_of(&variance, 2, units);
+}
+
+fn fn_matrix<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    f: fn(&MatrixData) -> Option<CalcResultType>,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    match &param.typ {
+        CalcResultType::Matrix(mat) => {
+            if let Some(result) = f(mat) {
+                stack.truncate(stack.len() - 1);
+                stack.push(CalcResult::new(result, fn_token_index));
+                Ok(())
+            } else {
+                // e.g. singular or not square matrix
+                Token::set_token_error_flag_by_index(fn_token_index, tokens);
+                Err(CalcError::InvalidOperation)
+            }
+        }
+        _ => {
+            param.set_token_error_flag(tokens);
+            Err(CalcError::InvalidOperation)
+        }
+    }
+}
+
+fn fn_identity<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 1 || stack.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let param = &stack[stack.len() - 1];
+    let n = match &param.typ {
+        CalcResultType::Number(n) if n.trunc() == *n => n.to_usize(),
+        _ => None,
+    };
+    // bigger matrices could not be rendered anyway
+    if let Some(n) = n.filter(|it| *it > 0 && *it <= MAX_LINE_COUNT) {
+        stack.truncate(stack.len() - 1);
+        stack.push(CalcResult::new(
+            CalcResultType::Matrix(MatrixData::identity(n)),
+            fn_token_index,
+        ));
+        Ok(())
+    } else {
+        param.set_token_error_flag(tokens);
+        Err(CalcError::InvalidOperation)
+    }
+}
+
+fn fn_solve<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let a = &stack[stack.len() - 2];
+    let b = &stack[stack.len() - 1];
+    match (&a.typ, &b.typ) {
+        (CalcResultType::Matrix(a), CalcResultType::Matrix(b)) => {
+            if let Some(x) = a.solve(b) {
+                stack.truncate(stack.len() - 2);
+                stack.push(CalcResult::new(CalcResultType::Matrix(x), fn_token_index));
+                Ok(())
+            } else {
+                Token::set_token_error_flag_by_index(fn_token_index, tokens);
+                Err(CalcError::InvalidOperation)
+            }
+        }
+        (CalcResultType::Matrix(_), _) => {
+            b.set_token_error_flag(tokens);
+            Err(CalcError::InvalidOperation)
+        }
+        (_, CalcResultType::Matrix(_)) => {
+            a.set_token_error_flag(tokens);
+            Err(CalcError::InvalidOperation)
+        }
+        _ => {
+            a.set_token_error_flag(tokens);
+            b.set_token_error_flag(tokens);
+            Err(CalcError::InvalidOperation)
+        }
+    }
+}
+
+fn fn_user_defined<'text_ptr>(
+    var_index: usize,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    units: &Units,
+    vars: &Variables,
+) -> Result<(), CalcError> {
+    let user_fn = if let Some(user_fn) = vars
+        .get(var_index)
+        .and_then(|it| it.as_ref())
+        .and_then(|it| it.user_fn.as_ref())
+    {
+        user_fn
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::UnknownVariable);
+    };
+    if arg_count != user_fn.params.len() || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    // the body can see the variables above the definition and the parameters
+    let mut local_vars: Vec<Option<Variable>> = vars
+        .iter()
+        .enumerate()
+        .map(|(i, var)| {
+            if i < var_index || is_aggregate_variable(i) {
+                var.clone()
+            } else {
+                None
+            }
+        })
+        .collect();
+    for (param, arg) in user_fn.params.iter().zip(&stack[stack.len() - arg_count..]) {
+        local_vars.push(Some(Variable {
+            name: param.clone(),
+            value: Ok(arg.clone()),
+            user_fn: None,
+        }));
+    }
+
+    let allocator = Bump::new();
+    let mut body_tokens = Vec::with_capacity(32);
+    let mut shunting_output_stack = Vec::with_capacity(32);
+    TokenParser::parse_line(
+        &user_fn.body,
+        &local_vars,
+        &mut body_tokens,
+        units,
+        local_vars.len(),
+        &allocator,
+    );
+    ShuntingYard::shunting_yard(&mut body_tokens, &mut shunting_output_stack);
+    let result = evaluate_tokens(
+        &mut body_tokens,
+        &mut shunting_output_stack,
+        &local_vars,
+        units,
+    );
+    match result {
+        Ok(Some(result)) => {
+            stack.truncate(stack.len() - arg_count);
+            stack.push(CalcResult::new(result.result.typ, fn_token_index));
+            Ok(())
+        }
+        // the error of the body, e.g. a division by zero for the given arguments
+        Err(err) => {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            Err(err)
+        }
+        Ok(None) => {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            Err(CalcError::InvalidOperation)
+        }
+    }
+}
+
+fn fn_if<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> Result<(), CalcError> {
+    if arg_count != 3 || stack.len() < 3 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return Err(CalcError::InvalidOperation);
+    }
+    let cond = &stack[stack.len() - 3];
+    let result = match &cond.typ {
+        CalcResultType::Boolean(true) => stack[stack.len() - 2].typ.clone(),
+        CalcResultType::Boolean(false) => stack[stack.len() - 1].typ.clone(),
+        _ => {
+            cond.set_token_error_flag(tokens);
+            return Err(CalcError::InvalidOperation);
+        }
+    };
+    stack.truncate(stack.len() - 3);
+    stack.push(CalcResult::new(result, fn_token_index));
+    Ok(())
+}
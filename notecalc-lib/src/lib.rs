@@ -13,6 +13,7 @@
     clippy::all
 )]
 
+use std::cmp::Ordering;
 use std::io::Cursor;
 use std::mem::MaybeUninit;
 use std::ops::Range;
@@ -25,27 +26,36 @@ use strum_macros::EnumDiscriminants;
 use helper::*;
 
 use crate::calc::{
-    add_op, evaluate_tokens, CalcResult, CalcResultType, EvaluationResult, ShuntingYardResult,
+    add_op, compare_values, dec, decayed, divide_op, evaluate_tokens, multiply_op, CalcError,
+    CalcResult, CalcResultType, EvaluationResult, ShuntingYardResult,
 };
 use crate::consts::{LINE_NUM_CONSTS, LINE_NUM_CONSTS2};
 use crate::editor::editor::{
     Editor, EditorInputEvent, InputModifiers, Pos, RowModificationType, Selection,
 };
 use crate::editor::editor_content::EditorContent;
+use crate::functions::FnType;
 use crate::matrix::MatrixData;
 use crate::renderer::{get_int_frac_part_len, render_result, render_result_into};
 use crate::shunting_yard::ShuntingYard;
 use crate::token_parser::{OperatorTokenType, Token, TokenParser, TokenType};
+use crate::units::consts::{
+    UnitDimensionExponent, BASE_UNIT_DIMENSION_COUNT, EMPTY_UNIT_DIMENSIONS,
+};
+use crate::units::currency::parse_exchange_rate;
 use crate::units::units::Units;
+use rust_decimal::Decimal;
 
 mod functions;
 mod matrix;
+mod rational;
 mod shunting_yard;
 mod token_parser;
 pub mod units;
 
 pub mod calc;
 pub mod consts;
+pub mod datetime;
 pub mod editor;
 pub mod renderer;
 
@@ -60,7 +70,25 @@ const CHANGE_RESULT_PULSE_END_COLOR: u32 = 0xFFFFFF_55;
 const REFERENCE_PULSE_PULSE_START_COLOR: u32 = 0x00FF7F_33;
 const MIN_RESULT_PANEL_WIDTH: usize = 7;
 const DEFAULT_RESULT_PANEL_WIDTH_PERCENT: usize = 70;
+const DEFAULT_RESULT_PRECISION: ResultPrecision = ResultPrecision::DecimalPlaces(4);
+/// a Decimal can't have more decimal places
+const MAX_RESULT_PRECISION: usize = 28;
 const SUM_VARIABLE_INDEX: usize = MAX_LINE_COUNT;
+const AVG_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 1;
+const COUNT_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 2;
+const MIN_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 3;
+const MAX_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 4;
+const PROD_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 5;
+/// implicit variables calculated from the results above them, until the previous '--' line
+const AGGREGATE_VARIABLES: [(&[char], usize); 6] = [
+    (&['s', 'u', 'm'], SUM_VARIABLE_INDEX),
+    (&['a', 'v', 'g'], AVG_VARIABLE_INDEX),
+    (&['c', 'o', 'u', 'n', 't'], COUNT_VARIABLE_INDEX),
+    (&['m', 'i', 'n'], MIN_VARIABLE_INDEX),
+    (&['m', 'a', 'x'], MAX_VARIABLE_INDEX),
+    (&['p', 'r', 'o', 'd'], PROD_VARIABLE_INDEX),
+];
+pub const VARIABLE_COUNT: usize = MAX_LINE_COUNT + AGGREGATE_VARIABLES.len();
 const MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT: usize = 2;
 const ACTIVE_LINE_REF_HIGHLIGHT_COLORS: [u32; 9] = [
     0xFFD300, 0xDE3163, 0x73c2fb, 0xc7ea46, 0x702963, 0x997950, 0x777b73, 0xFC6600, 0xED2939,
@@ -79,12 +107,9 @@ pub mod helper {
     use crate::calc::CalcResultType;
     pub use crate::{MAX_LINE_COUNT, *};
 
-    pub fn create_vars() -> [Option<Variable>; MAX_LINE_COUNT + 1] {
-        let mut vars = [None; MAX_LINE_COUNT + 1];
-        vars[SUM_VARIABLE_INDEX] = Some(Variable {
-            name: Box::from(&['s', 'u', 'm'][..]),
-            value: Err(()),
-        });
+    pub fn create_vars() -> [Option<Variable>; VARIABLE_COUNT] {
+        let mut vars = [None; VARIABLE_COUNT];
+        init_aggregate_variables(&mut vars);
         return vars;
     }
 
@@ -763,8 +788,129 @@ impl<'a> RenderBuckets<'a> {
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum ResultFormat {
     Bin,
+    Oct,
     Dec,
     Hex,
+    /// complex numbers are shown as magnitude and angle
+    Polar,
+    /// mixed numbers, e.g. 2 3/8 in
+    Fraction,
+    /// time quantities as 1:30:15 or 1d 4h 30min
+    Duration,
+    /// e.g. 1.2e-9
+    Scientific,
+    /// the exponent is a multiple of 3, e.g. 120e-9 or 120 nm
+    Engineering,
+}
+
+impl ResultFormat {
+    /// Alt+Left
+    fn prev(self) -> ResultFormat {
+        match self {
+            ResultFormat::Bin => ResultFormat::Fraction,
+            ResultFormat::Fraction => ResultFormat::Duration,
+            ResultFormat::Duration => ResultFormat::Scientific,
+            ResultFormat::Scientific => ResultFormat::Engineering,
+            ResultFormat::Engineering => ResultFormat::Polar,
+            ResultFormat::Polar => ResultFormat::Oct,
+            ResultFormat::Oct => ResultFormat::Hex,
+            ResultFormat::Hex => ResultFormat::Dec,
+            ResultFormat::Dec => ResultFormat::Bin,
+        }
+    }
+
+    /// Alt+Right
+    fn next(self) -> ResultFormat {
+        match self {
+            ResultFormat::Bin => ResultFormat::Dec,
+            ResultFormat::Dec => ResultFormat::Hex,
+            ResultFormat::Hex => ResultFormat::Oct,
+            ResultFormat::Oct => ResultFormat::Polar,
+            ResultFormat::Polar => ResultFormat::Engineering,
+            ResultFormat::Engineering => ResultFormat::Scientific,
+            ResultFormat::Scientific => ResultFormat::Duration,
+            ResultFormat::Duration => ResultFormat::Fraction,
+            ResultFormat::Fraction => ResultFormat::Bin,
+        }
+    }
+
+    /// the format cycle skips the formats which would not change the result, e.g. Polar for reals.
+    /// Everything applies to an empty or invalid line, its result is not known yet
+    fn applies_to(self, result: &LineResult, units: &Units) -> bool {
+        match result {
+            Ok(Some(result)) => self.applies_to_type(&result.typ, units),
+            _ => true,
+        }
+    }
+
+    fn applies_to_type(self, typ: &CalcResultType, units: &Units) -> bool {
+        match typ {
+            // the cells are rendered in the format of the matrix
+            CalcResultType::Matrix(mat) => mat
+                .cells
+                .iter()
+                .any(|cell| self.applies_to_type(&cell.typ, units)),
+            _ => match self {
+                ResultFormat::Dec => true,
+                ResultFormat::Bin | ResultFormat::Oct | ResultFormat::Hex => matches!(
+                    typ,
+                    CalcResultType::Number(..)
+                        | CalcResultType::BigInt(..)
+                        | CalcResultType::Rational(..)
+                ),
+                ResultFormat::Fraction => matches!(
+                    typ,
+                    CalcResultType::Number(..)
+                        | CalcResultType::Quantity(..)
+                        | CalcResultType::Rational(..)
+                ),
+                ResultFormat::Scientific | ResultFormat::Engineering => matches!(
+                    typ,
+                    CalcResultType::Number(..)
+                        | CalcResultType::Quantity(..)
+                        | CalcResultType::BigInt(..)
+                        | CalcResultType::Rational(..)
+                ),
+                ResultFormat::Duration => matches!(
+                    typ,
+                    CalcResultType::Quantity(_, unit)
+                        if unit.dimensions == units.parse(&['s']).0.dimensions
+                ),
+                ResultFormat::Polar => matches!(typ, CalcResultType::Complex(..)),
+            },
+        }
+    }
+}
+
+/// Set by a "@2dp" or "@3sf" suffix at the end of the line
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ResultPrecision {
+    DecimalPlaces(usize),
+    SignificantFigures(usize),
+}
+
+impl ResultPrecision {
+    fn increased(self) -> ResultPrecision {
+        match self {
+            ResultPrecision::DecimalPlaces(n) => {
+                ResultPrecision::DecimalPlaces((n + 1).min(MAX_RESULT_PRECISION))
+            }
+            ResultPrecision::SignificantFigures(n) => {
+                ResultPrecision::SignificantFigures((n + 1).min(MAX_RESULT_PRECISION))
+            }
+        }
+    }
+
+    fn decreased(self) -> ResultPrecision {
+        match self {
+            ResultPrecision::DecimalPlaces(n) => {
+                ResultPrecision::DecimalPlaces(n.saturating_sub(1))
+            }
+            ResultPrecision::SignificantFigures(n) => {
+                ResultPrecision::SignificantFigures((n - 1).max(1))
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -772,6 +918,7 @@ pub struct LineData {
     // has to be pub because of external tests...
     pub line_id: usize,
     result_format: ResultFormat,
+    precision: Option<ResultPrecision>,
 }
 
 impl Default for LineData {
@@ -779,6 +926,7 @@ impl Default for LineData {
         LineData {
             line_id: 0,
             result_format: ResultFormat::Dec,
+            precision: None,
         }
     }
 }
@@ -1097,13 +1245,21 @@ pub struct EditorObject {
     rendered_h: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Variable {
     pub name: Box<[char]>,
     pub value: Result<CalcResult, ()>,
+    pub user_fn: Option<UserFunction>,
+}
+
+/// e.g. `area(w, h) = w * h`, the name of the function is the name of its variable
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UserFunction {
+    pub params: Vec<Box<[char]>>,
+    pub body: Box<[char]>,
 }
 
-type LineResult = Result<Option<CalcResult>, ()>;
+type LineResult = Result<Option<CalcResult>, CalcError>;
 type Variables = [Option<Variable>];
 
 #[derive(Debug)]
@@ -1215,10 +1371,8 @@ impl NoteCalcApp {
         for v in vars.iter_mut() {
             *v = None;
         }
-        vars[SUM_VARIABLE_INDEX] = Some(Variable {
-            name: Box::from(&['s', 'u', 'm'][..]),
-            value: Err(()),
-        });
+        init_aggregate_variables(vars);
+        units.clear_declarations();
         self.render_data.clear();
         self.editor_objs_referencing_current_line.clear();
         self.process_and_render_tokens(
@@ -1331,7 +1485,7 @@ impl NoteCalcApp {
                         vars,
                         &units,
                         need_matrix_renderer,
-                        Some(4),
+                        Some(DEFAULT_RESULT_PRECISION),
                     );
                     // don't highlight refs in the current row as they will be pulsing in different colors
                     if editor.get_selection().get_cursor_pos().row != r.editor_y.as_usize() {
@@ -1489,6 +1643,8 @@ impl NoteCalcApp {
             allocator,
         );
 
+        render_error_of_active_line(render_buckets, results, &editor, &gr);
+
         let mut tmp = ResultRender::new(SmallVec::with_capacity(MAX_LINE_COUNT));
 
         render_results_into_buf_and_calc_len(
@@ -1498,7 +1654,7 @@ impl NoteCalcApp {
             &mut tmp,
             &editor_content,
             gr,
-            Some(4),
+            Some(DEFAULT_RESULT_PRECISION),
         );
         tmp.max_len = create_render_commands_for_results_and_render_matrices(
             &tmp,
@@ -1507,7 +1663,7 @@ impl NoteCalcApp {
             render_buckets,
             result_buffer,
             gr,
-            Some(4),
+            Some(DEFAULT_RESULT_PRECISION),
         )
         .max(tmp.max_len);
         gr.longest_rendered_result_len = tmp.max_len;
@@ -2337,31 +2493,62 @@ impl NoteCalcApp {
         fn handle_input_with_alt<'b>(
             app: &mut NoteCalcApp,
             input: EditorInputEvent,
+            results: &Results,
+            units: &Units,
         ) -> Option<RowModificationType> {
-            if input == EditorInputEvent::Left {
+            if input == EditorInputEvent::Left || input == EditorInputEvent::Right {
                 let selection = app.editor.get_selection();
                 let (start, end) = selection.get_range();
                 for row_i in start.row..=end.row {
-                    let new_format = match &app.editor_content.get_data(row_i).result_format {
-                        ResultFormat::Bin => ResultFormat::Hex,
-                        ResultFormat::Dec => ResultFormat::Bin,
-                        ResultFormat::Hex => ResultFormat::Dec,
-                    };
+                    let result = &results[content_y(row_i)];
+                    let mut new_format = app.editor_content.get_data(row_i).result_format;
+                    // Dec applies to everything, so it always terminates
+                    loop {
+                        new_format = if input == EditorInputEvent::Left {
+                            new_format.prev()
+                        } else {
+                            new_format.next()
+                        };
+                        if new_format.applies_to(result, units) {
+                            break;
+                        }
+                    }
                     app.editor_content.mut_data(row_i).result_format = new_format;
                 }
                 None
-            } else if input == EditorInputEvent::Right {
+            } else if input == EditorInputEvent::Char('.') || input == EditorInputEvent::Char(',') {
+                // more or less decimal places, the precision is saved into the text as a suffix
                 let selection = app.editor.get_selection();
                 let (start, end) = selection.get_range();
                 for row_i in start.row..=end.row {
-                    let new_format = match &app.editor_content.get_data(row_i).result_format {
-                        ResultFormat::Bin => ResultFormat::Dec,
-                        ResultFormat::Dec => ResultFormat::Hex,
-                        ResultFormat::Hex => ResultFormat::Bin,
+                    if app
+                        .editor_content
+                        .get_line_valid_chars(row_i)
+                        .iter()
+                        .all(|it| it.is_ascii_whitespace())
+                    {
+                        continue;
+                    }
+                    let precision = app
+                        .editor_content
+                        .get_data(row_i)
+                        .precision
+                        .unwrap_or(DEFAULT_RESULT_PRECISION);
+                    let new_precision = if input == EditorInputEvent::Char('.') {
+                        precision.increased()
+                    } else {
+                        precision.decreased()
                     };
-                    app.editor_content.mut_data(row_i).result_format = new_format;
+                    app.set_precision_suffix(row_i, new_precision);
                 }
-                None
+                let content = &app.editor_content;
+                let clamp = |pos: Pos| pos.with_column(pos.column.min(content.line_len(pos.row)));
+                let selection = Selection {
+                    start: clamp(selection.start),
+                    end: selection.end.map(clamp),
+                };
+                app.editor.set_selection_save_col(selection);
+                Some(RowModificationType::AllLinesFrom(start.row))
             } else if input == EditorInputEvent::Up {
                 let cur_pos = app.editor.get_selection().get_cursor_pos();
                 let rows = if let Some(selector_row) = app.line_reference_chooser {
@@ -2408,7 +2595,7 @@ impl NoteCalcApp {
         ////////////////////////////////////////////////////
         let prev_row = self.editor.get_selection().get_cursor_pos().row;
         let modif = if self.matrix_editing.is_none() && modifiers.alt {
-            handle_input_with_alt(&mut *self, input)
+            handle_input_with_alt(&mut *self, input, results, units)
         } else if self.matrix_editing.is_some() {
             self.handle_matrix_editor_input(input, modifiers);
             if self.matrix_editing.is_none() {
@@ -2424,7 +2611,7 @@ impl NoteCalcApp {
                 None
             }
         } else {
-            if self.handle_completion(&input, editor_objs, vars) {
+            if self.handle_completion(&input, editor_objs, vars, units) {
                 Some(RowModificationType::SingleLine(prev_row))
             } else if let Some(modif_type) = self.handle_obj_deletion(&input, editor_objs) {
                 Some(modif_type)
@@ -2439,7 +2626,10 @@ impl NoteCalcApp {
                         &result,
                         &self.editor_content.get_data(row).result_format,
                         false,
-                        Some(4),
+                        self.editor_content
+                            .get_data(row)
+                            .precision
+                            .or(Some(DEFAULT_RESULT_PRECISION)),
                         true,
                     ));
                 }
@@ -2563,6 +2753,14 @@ impl NoteCalcApp {
         ) -> (bool, EditorRowFlags) {
             // TODO avoid clone
             let prev_var_name = vars[editor_y.as_usize()].as_ref().map(|it| it.name.clone());
+            let was_user_fn = vars[editor_y.as_usize()]
+                .as_ref()
+                .map(|it| it.user_fn.is_some())
+                .unwrap_or(false);
+            let prev_exchange_rate = units.exchange_rate(editor_y.as_usize());
+            let prev_user_unit = units.user_unit(editor_y.as_usize());
+            // the units are copied into the tokens, so the declarations have to be applied before parsing
+            units.apply_declarations_above(editor_y.as_usize());
 
             tokens_per_lines[editor_y] = Some(parse_tokens(
                 line,
@@ -2574,6 +2772,7 @@ impl NoteCalcApp {
             let new_result = if let Some(tokens) = &mut tokens_per_lines[editor_y] {
                 let result = evaluate_tokens_and_save_result(
                     &mut *vars,
+                    units,
                     editor_y.as_usize(),
                     editor_content,
                     &mut tokens.tokens,
@@ -2616,22 +2815,96 @@ impl NoteCalcApp {
             }
 
             let curr_var_name = vars[editor_y.as_usize()].as_ref().map(|it| &it.name);
+            // user functions have no result to compare, and their body might depend on
+            // the variables above them, so their callers are always recalculated
+            let user_fn_has_changed = was_user_fn
+                || vars[editor_y.as_usize()]
+                    .as_ref()
+                    .map(|it| it.user_fn.is_some())
+                    .unwrap_or(false);
             rows_to_recalc.merge(find_lines_that_affected_by_var_change(
-                result_has_changed,
+                result_has_changed || user_fn_has_changed,
                 curr_var_name,
                 prev_var_name,
                 tokens_per_lines,
                 editor_y.as_usize(),
             ));
 
-            rows_to_recalc.merge(find_sum_variable_name(
+            rows_to_recalc.merge(find_aggregate_variable_name(
                 tokens_per_lines,
                 editor_y.as_usize(),
             ));
+            if result_has_changed {
+                rows_to_recalc.merge(find_line_ranges_containing(
+                    tokens_per_lines,
+                    editor_y.as_usize(),
+                ));
+                rows_to_recalc.merge(find_prev_result_references(
+                    tokens_per_lines,
+                    results,
+                    editor_y.as_usize(),
+                ));
+            }
+            if units.exchange_rate(editor_y.as_usize()) != prev_exchange_rate
+                || units.user_unit(editor_y.as_usize()) != prev_user_unit
+            {
+                // the declarations are used by every line below them
+                rows_to_recalc.merge(EditorRowFlags::all_rows_starting_at(
+                    editor_y.as_usize() + 1,
+                ));
+            }
             return (result_has_changed, rows_to_recalc);
         }
 
-        fn find_sum_variable_name(tokens_per_lines: &AppTokens, editor_y: usize) -> EditorRowFlags {
+        /// the lines below until the next result might refer to this one through `prev`.
+        /// Structural edits (swapping, inserting, removing lines) recalculate
+        /// every line below them anyway, so only the in-place changes have to be tracked here
+        fn find_prev_result_references(
+            tokens_per_lines: &AppTokens,
+            results: &Results,
+            editor_y: usize,
+        ) -> EditorRowFlags {
+            let mut rows_to_recalc = EditorRowFlags::empty();
+            for (line_index, tokens) in tokens_per_lines.iter().skip(editor_y + 1).enumerate() {
+                let y = editor_y + 1 + line_index;
+                if let Some(tokens) = tokens {
+                    let refers_to_prev = tokens
+                        .tokens
+                        .iter()
+                        .any(|token| TokenParser::is_prev_result_name(token.ptr));
+                    if refers_to_prev {
+                        rows_to_recalc.merge(EditorRowFlags::single_row(y));
+                    }
+                }
+                if matches!(results[content_y(y)], Ok(Some(_))) {
+                    break;
+                }
+            }
+            return rows_to_recalc;
+        }
+
+        fn find_line_ranges_containing(
+            tokens_per_lines: &AppTokens,
+            editor_y: usize,
+        ) -> EditorRowFlags {
+            let mut rows_to_recalc = EditorRowFlags::empty();
+            for (line_index, tokens) in tokens_per_lines.iter().skip(editor_y + 1).enumerate() {
+                if let Some(tokens) = tokens {
+                    let contains_editor_y = tokens.tokens.iter().any(|token| {
+                        matches!(token.typ, TokenType::LineRange { from, to } if from <= editor_y && editor_y <= to)
+                    });
+                    if contains_editor_y {
+                        rows_to_recalc.merge(EditorRowFlags::single_row(editor_y + 1 + line_index));
+                    }
+                }
+            }
+            return rows_to_recalc;
+        }
+
+        fn find_aggregate_variable_name(
+            tokens_per_lines: &AppTokens,
+            editor_y: usize,
+        ) -> EditorRowFlags {
             let mut rows_to_recalc = EditorRowFlags::empty();
             'outer: for (line_index, tokens) in
                 tokens_per_lines.iter().skip(editor_y + 1).enumerate()
@@ -2643,7 +2916,7 @@ impl NoteCalcApp {
                                 break 'outer;
                             }
                             TokenType::Variable { var_index }
-                                if var_index == SUM_VARIABLE_INDEX =>
+                                if is_aggregate_variable(var_index) =>
                             {
                                 rows_to_recalc
                                     .merge(EditorRowFlags::single_row(editor_y + 1 + line_index));
@@ -2690,7 +2963,11 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 match token.typ {
-                                    TokenType::Variable { .. } if *token.ptr == *old_var_name => {
+                                    TokenType::Variable { .. }
+                                    | TokenType::Operator(OperatorTokenType::Fn {
+                                        typ: FnType::UserDefined(..),
+                                        ..
+                                    }) if *token.ptr == *old_var_name => {
                                         rows_to_recalc
                                             .merge(EditorRowFlags::single_row(editor_y + 1 + i));
                                     }
@@ -2707,7 +2984,11 @@ impl NoteCalcApp {
                             for token in &tokens.tokens {
                                 let recalc = match token.typ {
                                     TokenType::StringLiteral => var_name.starts_with(token.ptr),
-                                    TokenType::Variable { .. } => *token.ptr == *old_var_name,
+                                    TokenType::Variable { .. }
+                                    | TokenType::Operator(OperatorTokenType::Fn {
+                                        typ: FnType::UserDefined(..),
+                                        ..
+                                    }) => *token.ptr == *old_var_name,
                                     _ => false,
                                 };
                                 if recalc {
@@ -2727,7 +3008,11 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 let recalc = match token.typ {
-                                    TokenType::Variable { .. } if *token.ptr == **var_name => true,
+                                    TokenType::Variable { .. }
+                                    | TokenType::Operator(OperatorTokenType::Fn {
+                                        typ: FnType::UserDefined(..),
+                                        ..
+                                    }) if *token.ptr == **var_name => true,
                                     _ => false,
                                 };
                                 if recalc {
@@ -2743,7 +3028,7 @@ impl NoteCalcApp {
             return rows_to_recalc;
         }
 
-        let mut sum_is_null = true;
+        let mut aggregates_are_null = true;
         let mut dependant_rows = EditorRowFlags::empty();
         let mut result_change_flag = EditorRowFlags::empty();
         for editor_y in 0..self.editor_content.line_count().min(MAX_LINE_COUNT) {
@@ -2776,6 +3061,10 @@ impl NoteCalcApp {
                     y,
                     &mut self.updated_line_ref_obj_indices,
                 );
+                self.editor_content.mut_data(editor_y).precision =
+                    TokenParser::try_extract_precision_suffix(
+                        self.editor_content.get_line_valid_chars(editor_y),
+                    );
                 if result_has_changed {
                     result_change_flag.merge(EditorRowFlags::single_row(editor_y));
                 }
@@ -2788,18 +3077,12 @@ impl NoteCalcApp {
                 .get_line_valid_chars(editor_y)
                 .starts_with(&['-', '-'])
             {
-                sum_is_null = true;
+                aggregates_are_null = true;
             }
 
             match &results[content_y(editor_y)] {
                 Ok(Some(result)) => {
-                    sum_result(
-                        vars[SUM_VARIABLE_INDEX]
-                            .as_mut()
-                            .expect("SUM always exists"),
-                        result,
-                        &mut sum_is_null,
-                    );
+                    aggregate_result(vars, result, &mut aggregates_are_null);
                 }
                 Err(_) | Ok(None) => {}
             }
@@ -2882,7 +3165,7 @@ impl NoteCalcApp {
                             var_index
                         }
                         TokenType::Variable { var_index }
-                            if var_index != SUM_VARIABLE_INDEX
+                            if !is_aggregate_variable(var_index)
                                 && already_added.is_false(var_index)
                                 && token.ptr == editor_obj_name =>
                         {
@@ -3057,11 +3340,38 @@ impl NoteCalcApp {
         return result_str;
     }
 
+    /// Replaces or appends the "@2dp" like suffix of the line, so the precision is saved with the text
+    fn set_precision_suffix(&mut self, row: usize, precision: ResultPrecision) {
+        let line = self.editor_content.get_line_valid_chars(row);
+        let end = line.len();
+        let (start, separator) = if TokenParser::try_extract_precision_suffix(line).is_some() {
+            (line.iter().rposition(|it| *it == '@').unwrap_or(end), "")
+        } else if line
+            .last()
+            .map(|it| it.is_ascii_whitespace())
+            .unwrap_or(true)
+        {
+            (end, "")
+        } else {
+            (end, " ")
+        };
+        let suffix = match precision {
+            ResultPrecision::DecimalPlaces(n) => format!("{}@{}dp", separator, n),
+            ResultPrecision::SignificantFigures(n) => format!("{}@{}sf", separator, n),
+        };
+        self.editor.set_selection_save_col(Selection::range(
+            Pos::from_row_column(row, start),
+            Pos::from_row_column(row, end),
+        ));
+        self.editor.insert_text(&suffix, &mut self.editor_content);
+    }
+
     fn handle_completion<'b>(
         &mut self,
         input: &EditorInputEvent,
         editor_objects: &mut EditorObjects,
         vars: &Variables,
+        units: &Units,
     ) -> bool {
         let cursor_pos = self.editor.get_selection();
         if *input != EditorInputEvent::Tab || cursor_pos.get_cursor_pos().column == 0 {
@@ -3148,44 +3458,37 @@ impl NoteCalcApp {
             }
             (begin_index, len)
         };
-        // find the best match
-        let mut matched_var_index = None;
-        for (var_i, var) in vars[0..cursor_pos.row].iter().enumerate() {
-            if var.is_none() {
-                continue;
-            }
+        // find the best match among the variables and the units declared in the note
+        let user_unit_names = units.user_unit_names(cursor_pos.row);
+        let names = vars[0..cursor_pos.row]
+            .iter()
+            .filter_map(|var| var.as_ref().map(|var| &var.name[..]))
+            .chain(user_unit_names.iter().copied());
+        let mut matched_name = None;
+        for name in names {
             let mut match_len = 0;
-            for (var_ch, actual_ch) in var
-                .as_ref()
-                .unwrap()
-                .name
+            for (name_ch, actual_ch) in name
                 .iter()
                 .zip(&line[begin_index..begin_index + expected_len])
             {
-                if *var_ch != *actual_ch {
+                if *name_ch != *actual_ch {
                     break;
                 }
                 match_len += 1;
             }
             if expected_len == match_len {
-                if matched_var_index.is_some() {
+                if matched_name.is_some() {
                     // multiple match, don't autocomplete
-                    matched_var_index = None;
+                    matched_name = None;
                     break;
                 } else {
-                    matched_var_index = Some(var_i);
+                    matched_name = Some(name);
                 }
             }
         }
 
-        if let Some(matched_var_index) = matched_var_index {
-            for ch in vars[matched_var_index]
-                .as_ref()
-                .unwrap()
-                .name
-                .iter()
-                .skip(expected_len)
-            {
+        if let Some(matched_name) = matched_name {
+            for ch in matched_name.iter().skip(expected_len) {
                 self.editor.handle_input(
                     EditorInputEvent::Char(*ch),
                     InputModifiers::none(),
@@ -3800,7 +4103,7 @@ fn highlight_active_line_refs<'text_ptr>(
         match editor_obj.typ {
             EditorObjectType::LineReference { var_index }
             | EditorObjectType::Variable { var_index }
-                if var_index != SUM_VARIABLE_INDEX =>
+                if !is_aggregate_variable(var_index) =>
             {
                 let color = if let Some(color) = colors[var_index] {
                     color
@@ -3837,7 +4140,7 @@ fn render_tokens<'text_ptr>(
     vars: &Variables,
     units: &Units,
     need_matrix_renderer: bool,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
 ) {
     editor_objects.clear();
     let cursor_pos = editor.get_selection().get_cursor_pos();
@@ -3864,7 +4167,6 @@ fn render_tokens<'text_ptr>(
                 editor_objects,
                 &editor,
                 &matrix_editing,
-                decimal_count,
             );
         } else if let (TokenType::Variable { var_index }, true) = (&token.typ, need_matrix_renderer)
         {
@@ -3896,14 +4198,8 @@ fn render_tokens<'text_ptr>(
         {
             let var = vars[*var_index].as_ref().unwrap();
 
-            let (rendered_width, rendered_height) = render_result_inside_editor(
-                units,
-                render_buckets,
-                &var.value,
-                r,
-                gr,
-                decimal_count,
-            );
+            let (rendered_width, rendered_height) =
+                render_result_inside_editor(units, render_buckets, &var.value, r, gr, precision);
 
             let var_name_len = var.name.len();
             editor_objects.push(EditorObject {
@@ -4026,7 +4322,19 @@ fn draw_right_gutter_num_prefixes(
                 column: result_gutter_x,
             });
         }
-        ResultFormat::Dec => {}
+        ResultFormat::Oct => {
+            render_buckets.operators.push(RenderUtf8TextMsg {
+                text: &['0', 'o'],
+                row: r.render_y,
+                column: result_gutter_x,
+            });
+        }
+        ResultFormat::Dec
+        | ResultFormat::Polar
+        | ResultFormat::Fraction
+        | ResultFormat::Duration
+        | ResultFormat::Scientific
+        | ResultFormat::Engineering => {}
     }
 }
 
@@ -4049,15 +4357,67 @@ fn highlight_current_line(
     }
 }
 
+fn evaluate_unit_definition(
+    body: &[char],
+    vars: &Variables,
+    units: &Units,
+    editor_y: usize,
+) -> Option<(Decimal, [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT])> {
+    let allocator = Bump::new();
+    let mut tokens = Vec::with_capacity(8);
+    let mut shunting_output_stack = Vec::with_capacity(8);
+    TokenParser::parse_line(body, vars, &mut tokens, units, editor_y, &allocator);
+    ShuntingYard::shunting_yard(&mut tokens, &mut shunting_output_stack);
+    let result = evaluate_tokens(&mut tokens, &mut shunting_output_stack, vars, units)
+        .ok()??
+        .result;
+    let result = decayed(&result).unwrap_or(result);
+    match result.typ {
+        CalcResultType::Number(num) => Some((num, EMPTY_UNIT_DIMENSIONS)),
+        CalcResultType::Quantity(num, unit) => Some((num, unit.dimensions)),
+        _ => None,
+    }
+}
+
 fn evaluate_tokens_and_save_result<'text_ptr>(
     vars: &mut Variables,
+    units: &Units,
     editor_y: usize,
     editor_content: &EditorContent<LineData>,
     tokens: &mut [Token<'text_ptr>],
     shunting_output_stack: &mut Vec<ShuntingYardResult>,
     line: &[char],
-) -> Result<Option<EvaluationResult>, ()> {
-    let result = evaluate_tokens(tokens, shunting_output_stack, &vars);
+) -> Result<Option<EvaluationResult>, CalcError> {
+    let exchange_rate = parse_exchange_rate(line);
+    let is_exchange_rate = exchange_rate.is_some();
+    units.set_exchange_rate(editor_y, exchange_rate);
+    units.remove_user_unit(editor_y);
+    if is_exchange_rate {
+        // e.g. "1 EUR = 1.08 USD", it is applied to the lines below
+        vars[editor_y] = None;
+        return Ok(None);
+    }
+    if let Some((name, body)) = TokenParser::try_extract_unit_definition(line) {
+        // e.g. "unit sprint = 2 week", it can be used in the lines below
+        vars[editor_y] = None;
+        let (value, dimensions) = evaluate_unit_definition(body, vars, units, editor_y)
+            .ok_or(CalcError::InvalidUnitDefinition)?;
+        units.set_user_unit(editor_y, name, value, dimensions)?;
+        return Ok(None);
+    }
+    if let Some((name, params, body)) = TokenParser::try_extract_fn_definition(line) {
+        // function definitions don't have results, the body is evaluated when it is called
+        vars[editor_y] = Some(Variable {
+            name: Box::from(name),
+            value: Err(()),
+            user_fn: Some(UserFunction {
+                params: params.into_iter().map(Box::from).collect(),
+                body: Box::from(body),
+            }),
+        });
+        return Ok(None);
+    }
+    let result = evaluate_tokens(tokens, shunting_output_stack, &vars, units);
     if let Ok(Some(result)) = &result {
         fn replace_or_insert_var(
             vars: &mut Variables,
@@ -4068,10 +4428,12 @@ fn evaluate_tokens_and_save_result<'text_ptr>(
             if let Some(var) = &mut vars[editor_y] {
                 var.name = Box::from(var_name);
                 var.value = Ok(result);
+                var.user_fn = None;
             } else {
                 vars[editor_y] = Some(Variable {
                     name: Box::from(var_name),
                     value: Ok(result),
+                    user_fn: None,
                 });
             };
         }
@@ -4120,6 +4482,7 @@ fn evaluate_tokens_and_save_result<'text_ptr>(
             let var_name: Vec<char> = format!("&[{}]", line_id).chars().collect();
             var.name = Box::from(var_name);
             var.value = Err(());
+            var.user_fn = None;
         } else {
             vars[editor_y] = None;
         }
@@ -4127,22 +4490,83 @@ fn evaluate_tokens_and_save_result<'text_ptr>(
     result
 }
 
-fn sum_result(sum_var: &mut Variable, result: &CalcResult, sum_is_null: &mut bool) {
-    if *sum_is_null {
-        sum_var.value = Ok(result.clone());
-        *sum_is_null = false;
-    } else {
-        sum_var.value = match &sum_var.value {
-            Ok(current_sum) => {
-                if let Some(ok) = add_op(&current_sum, &result) {
-                    Ok(ok)
-                } else {
-                    Err(())
-                }
-            }
+fn is_aggregate_variable(var_index: usize) -> bool {
+    AGGREGATE_VARIABLES
+        .iter()
+        .any(|(_name, index)| *index == var_index)
+}
+
+fn init_aggregate_variables(vars: &mut Variables) {
+    for (name, index) in AGGREGATE_VARIABLES.iter() {
+        vars[*index] = Some(Variable {
+            name: Box::from(*name),
+            value: Err(()),
+            user_fn: None,
+        });
+    }
+}
+
+fn aggregate_result(vars: &mut Variables, result: &CalcResult, aggregates_are_null: &mut bool) {
+    fn update(
+        vars: &mut Variables,
+        var_index: usize,
+        f: impl FnOnce(&CalcResult) -> Option<CalcResult>,
+    ) {
+        let var = vars[var_index].as_mut().expect("aggregates always exist");
+        var.value = match &var.value {
+            Ok(current) => f(current).ok_or(()),
             _ => Err(()),
+        };
+    }
+
+    if *aggregates_are_null {
+        for (_name, var_index) in AGGREGATE_VARIABLES.iter() {
+            vars[*var_index]
+                .as_mut()
+                .expect("aggregates always exist")
+                .value = Ok(result.clone());
         }
+        vars[COUNT_VARIABLE_INDEX]
+            .as_mut()
+            .expect("aggregates always exist")
+            .value = Ok(CalcResult::new(CalcResultType::Number(dec(1)), 0));
+        *aggregates_are_null = false;
+        return;
     }
+    update(vars, SUM_VARIABLE_INDEX, |sum| add_op(sum, result));
+    update(vars, PROD_VARIABLE_INDEX, |prod| multiply_op(prod, result));
+    update(vars, MIN_VARIABLE_INDEX, |min| {
+        match compare_values(result, min)? {
+            Ordering::Less => Some(result.clone()),
+            _ => Some(min.clone()),
+        }
+    });
+    update(vars, MAX_VARIABLE_INDEX, |max| {
+        match compare_values(result, max)? {
+            Ordering::Greater => Some(result.clone()),
+            _ => Some(max.clone()),
+        }
+    });
+    update(vars, COUNT_VARIABLE_INDEX, |count| {
+        add_op(count, &CalcResult::new(CalcResultType::Number(dec(1)), 0))
+    });
+    let avg = match (
+        &vars[SUM_VARIABLE_INDEX]
+            .as_ref()
+            .expect("aggregates always exist")
+            .value,
+        &vars[COUNT_VARIABLE_INDEX]
+            .as_ref()
+            .expect("aggregates always exist")
+            .value,
+    ) {
+        (Ok(sum), Ok(count)) => divide_op(sum, count).ok_or(()),
+        _ => Err(()),
+    };
+    vars[AVG_VARIABLE_INDEX]
+        .as_mut()
+        .expect("aggregates always exist")
+        .value = avg;
 }
 
 fn render_matrix<'text_ptr>(
@@ -4156,8 +4580,6 @@ fn render_matrix<'text_ptr>(
     editor_objects: &mut Vec<EditorObject>,
     editor: &Editor,
     matrix_editing: &Option<MatrixEditing>,
-    // TODO: why unused?
-    _decimal_count: Option<usize>,
 ) -> usize {
     let mut text_width = 0;
     let mut end_token_index = token_index;
@@ -4260,7 +4682,10 @@ fn evaluate_selection(
                         &result.result,
                         &editor_content.get_data(sel.start.row).result_format,
                         result.there_was_unit_conversion,
-                        Some(4),
+                        editor_content
+                            .get_data(sel.start.row)
+                            .precision
+                            .or(Some(DEFAULT_RESULT_PRECISION)),
                         true,
                     );
                     return Some(result_str);
@@ -4294,7 +4719,10 @@ fn evaluate_selection(
                 sum,
                 &editor_content.get_data(sel.start.row).result_format,
                 false,
-                Some(4),
+                editor_content
+                    .get_data(sel.start.row)
+                    .precision
+                    .or(Some(DEFAULT_RESULT_PRECISION)),
                 true,
             );
             return Some(result_str);
@@ -4310,11 +4738,11 @@ fn evaluate_text<'text_ptr>(
     tokens: &mut Vec<Token<'text_ptr>>,
     editor_y: usize,
     allocator: &'text_ptr Bump,
-) -> Result<Option<EvaluationResult>, ()> {
+) -> Result<Option<EvaluationResult>, CalcError> {
     TokenParser::parse_line(text, vars, tokens, &units, editor_y, allocator);
     let mut shunting_output_stack = Vec::with_capacity(4);
     ShuntingYard::shunting_yard(tokens, &mut shunting_output_stack);
-    return evaluate_tokens(tokens, &mut shunting_output_stack, &vars);
+    return evaluate_tokens(tokens, &mut shunting_output_stack, &vars, &units);
 }
 
 fn render_matrix_obj<'text_ptr>(
@@ -4507,7 +4935,7 @@ fn render_matrix_result<'text_ptr>(
     render_buckets: &mut RenderBuckets<'text_ptr>,
     prev_mat_result_lengths: Option<&ResultLengths>,
     rendered_row_height: usize,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
 ) -> usize {
     let start_x = render_x;
 
@@ -4525,8 +4953,7 @@ fn render_matrix_result<'text_ptr>(
         let mut tokens_per_cell: SmallVec<[String; 32]> = SmallVec::with_capacity(32);
 
         for cell in mat.cells.iter() {
-            let result_str =
-                render_result(units, cell, &ResultFormat::Dec, false, decimal_count, true);
+            let result_str = render_result(units, cell, &ResultFormat::Dec, false, precision, true);
             tokens_per_cell.push(result_str);
         }
         tokens_per_cell
@@ -4639,7 +5066,7 @@ fn render_result_inside_editor<'text_ptr>(
     result: &Result<CalcResult, ()>,
     r: &PerLineRenderData,
     gr: &GlobalRenderData,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
 ) -> (usize, usize) {
     return match &result {
         Ok(CalcResult {
@@ -4654,14 +5081,20 @@ fn render_result_inside_editor<'text_ptr>(
                 render_buckets,
                 None,
                 r.rendered_row_height,
-                decimal_count,
+                precision,
             );
             (rendered_width, mat.render_height())
         }
         Ok(result) => {
             // TODO: optimize string alloc
-            let result_str =
-                render_result(&units, result, &ResultFormat::Dec, false, Some(2), true);
+            let result_str = render_result(
+                &units,
+                result,
+                &ResultFormat::Dec,
+                false,
+                Some(ResultPrecision::DecimalPlaces(2)),
+                true,
+            );
             let text_len = result_str
                 .chars()
                 .count()
@@ -4717,7 +5150,7 @@ fn render_results_into_buf_and_calc_len<'text_ptr>(
     tmp: &mut ResultRender,
     editor_content: &EditorContent<LineData>,
     gr: &GlobalRenderData,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
 ) {
     let mut result_buffer_index = 0;
     // calc max length and render results into buffer
@@ -4766,7 +5199,10 @@ fn render_results_into_buf_and_calc_len<'text_ptr>(
                         &editor_content.get_data(editor_y.as_usize()).result_format,
                         false,
                         &mut c,
-                        decimal_count,
+                        editor_content
+                            .get_data(editor_y.as_usize())
+                            .precision
+                            .or(precision),
                         true,
                     );
                     let len = c.position() as usize;
@@ -4808,7 +5244,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
     render_buckets: &mut RenderBuckets<'text_ptr>,
     result_buffer: &'text_ptr [u8],
     gr: &GlobalRenderData,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
 ) -> usize {
     let mut prev_result_matrix_length = None;
     let mut matrix_len = 0;
@@ -4933,7 +5369,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
                         render_buckets,
                         prev_result_matrix_length.as_ref(),
                         gr.get_rendered_height(result_tmp.editor_y),
-                        decimal_count,
+                        precision,
                     );
                     if width > matrix_len {
                         matrix_len = width;
@@ -4989,7 +5425,14 @@ fn calc_matrix_max_lengths(units: &Units, mat: &MatrixData) -> ResultLengths {
         let mut tokens_per_cell: SmallVec<[String; 32]> = SmallVec::with_capacity(32);
 
         for cell in mat.cells.iter() {
-            let result_str = render_result(units, cell, &ResultFormat::Dec, false, Some(4), true);
+            let result_str = render_result(
+                units,
+                cell,
+                &ResultFormat::Dec,
+                false,
+                Some(DEFAULT_RESULT_PRECISION),
+                true,
+            );
             tokens_per_cell.push(result_str);
         }
         tokens_per_cell
@@ -5021,7 +5464,7 @@ fn draw_line_refs_and_vars_referenced_from_cur_row<'b>(
         match editor_obj.typ {
             EditorObjectType::LineReference { var_index }
             | EditorObjectType::Variable { var_index } => {
-                if var_index == SUM_VARIABLE_INDEX {
+                if is_aggregate_variable(var_index) {
                     continue;
                 }
                 let color = if highlighted.is_true(var_index) {
@@ -5066,7 +5509,12 @@ fn draw_token<'text_ptr>(
             TokenType::StringLiteral => &mut render_buckets.utf8_texts,
             TokenType::Variable { .. } => &mut render_buckets.variable,
             TokenType::LineReference { .. } => &mut render_buckets.variable,
+            TokenType::LineRange { .. } => &mut render_buckets.variable,
             TokenType::NumberLiteral(_) => &mut render_buckets.numbers,
+            TokenType::BigIntLiteral(_) => &mut render_buckets.numbers,
+            TokenType::ImaginaryLiteral(_) => &mut render_buckets.numbers,
+            TokenType::DateLiteral(_) => &mut render_buckets.numbers,
+            TokenType::DurationLiteral(..) => &mut render_buckets.numbers,
             TokenType::NumberErr => &mut render_buckets.number_errors,
             TokenType::Operator(OperatorTokenType::ApplyUnit(_)) => &mut render_buckets.units,
             TokenType::Unit(_) => &mut render_buckets.units,
@@ -5340,6 +5788,46 @@ fn render_selection_and_its_sum<'text_ptr>(
     }
 }
 
+/// the reason of the error is shown above the active line (below it in the first row)
+fn render_error_of_active_line(
+    render_buckets: &mut RenderBuckets,
+    results: &Results,
+    editor: &Editor,
+    gr: &GlobalRenderData,
+) {
+    if editor.get_selection().is_range().is_some() {
+        return;
+    }
+    let cursor_row = content_y(editor.get_selection().get_cursor_pos().row);
+    let err = if let Err(err) = &results[cursor_row] {
+        err
+    } else {
+        return;
+    };
+    if let Some(render_y) = gr.get_render_y(cursor_row) {
+        let rect_y = if render_y.as_usize() == 0 {
+            render_y.add(gr.get_rendered_height(cursor_row))
+        } else {
+            render_y.sub(1)
+        };
+        let msg: String = err
+            .to_string()
+            .chars()
+            .take(gr.current_editor_width)
+            .collect();
+        render_buckets.set_color(Layer::AboveText, 0xFFAAAA_FF);
+        render_buckets.draw_rect(
+            Layer::AboveText,
+            gr.left_gutter_width,
+            rect_y,
+            msg.chars().count(),
+            1,
+        );
+        render_buckets.set_color(Layer::AboveText, 0x000000_FF);
+        render_buckets.draw_string(Layer::AboveText, gr.left_gutter_width, rect_y, msg);
+    }
+}
+
 fn limit_result_gutter_x(current_x: usize, left_gutter_width: usize) -> usize {
     current_x.max(left_gutter_width + 4)
 }
@@ -5546,9 +6034,7 @@ mod main_tests {
         }
 
         fn mut_vars<'a>(&self) -> &'a mut [Option<Variable>] {
-            unsafe {
-                &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; MAX_LINE_COUNT + 1]))[..]
-            }
+            unsafe { &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; VARIABLE_COUNT]))[..] }
         }
 
         fn allocator<'a>(&self) -> &'a Bump {
@@ -7044,6 +7530,78 @@ sum",
         assert_results(&["2", "3"][..], &result_buffer);
     }
 
+    #[test]
+    fn test_user_defined_fn() {
+        let test = create_app2(35);
+        test.paste("area(w, h) = w * h\narea(3 m, 4 m)\narea(2, 5) + 1");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["", "12 m^2", "11"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_user_defined_fn_can_use_vars_above_it() {
+        let test = create_app2(35);
+        test.paste("rate = 2\ndouble(x) = x * rate\ndouble(5)");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["2", "", "10"][..], &result_buffer);
+
+        // change rate from 2 to 3
+        test.set_cursor_row_col(0, 8);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        test.input(EditorInputEvent::Char('3'), InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["3", "", "15"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_user_defined_fn_redefinition_recalcs_dependants() {
+        let test = create_app2(35);
+        test.paste("f(x) = x * 2\nf(3)");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["", "6"][..], &result_buffer);
+
+        test.set_cursor_row_col(0, 12);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        test.input(EditorInputEvent::Char('3'), InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["", "9"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_user_defined_fn_must_be_defined_above() {
+        let test = create_app2(35);
+        test.paste("f(3)\nf(x) = x * 2\nf(1, 2)");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["3", "", "Err"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_line_range_reference() {
+        let test = create_app2(35);
+        test.paste("1\n2\n\n3\nsum(&[1:4])\nmax(&[1]..&[4])\navg(&[4:1])");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["1", "2", "", "3", "6", "3", "2"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_line_range_reference_is_updated_when_a_line_in_it_changes() {
+        let test = create_app2(35);
+        test.paste("1\n2\n3\nsum(&[1:3])");
+        test.set_cursor_row_col(1, 1);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        test.input(EditorInputEvent::Char('5'), InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["1", "5", "3", "9"][..], &result_buffer);
+    }
+
     #[test]
     fn test_moving_line_does_not_change_its_lineref() {
         let test = create_app2(35);
@@ -7976,27 +8534,71 @@ sum",
     }
 
     #[test]
-    fn scroll_dragging_limit() {
+    fn test_error_message_of_active_line() {
         let test = create_app2(35);
-        test.repeated_paste("1\n", 39);
-        test.render();
-        test.click(test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH, 0);
-
-        assert_eq!(test.get_render_data().scroll_y, 0);
+        test.paste("1\n1 m + 1 s");
+        let error_msg = || {
+            OutputMessage::RenderString(RenderStringMsg {
+                text: "incompatible units: length and time".to_owned(),
+                row: canvas_y(0),
+                column: LEFT_GUTTER_MIN_WIDTH,
+            })
+        };
 
-        for i in 0..5 {
-            test.handle_drag(
-                test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH,
-                1 + i,
-            );
-            assert_eq!(test.get_render_data().scroll_y, 1 + i as usize);
-        }
-        test.handle_drag(test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH, 6);
-        // the scrollbar reached its bottom position, it won't go further down
-        assert_eq!(test.get_render_data().scroll_y, 5);
-    }
+        let mut render_buckets = RenderBuckets::new();
+        let mut result_buffer = [0; 128];
+        test.render_get_result_commands(&mut render_buckets, &mut result_buffer[..]);
+        let commands = &render_buckets.custom_commands[Layer::AboveText as usize];
+        test.contains(commands, 1, error_msg());
 
-    #[test]
+        test.input(EditorInputEvent::Up, InputModifiers::none());
+        let mut render_buckets = RenderBuckets::new();
+        test.render_get_result_commands(&mut render_buckets, &mut result_buffer[..]);
+        let commands = &render_buckets.custom_commands[Layer::AboveText as usize];
+        test.contains(commands, 0, error_msg());
+    }
+
+    #[test]
+    fn test_error_message_of_user_function_comes_from_its_body() {
+        let test = create_app2(35);
+        test.paste("recip(x) = 1 / x\nrecip(0)");
+        let mut render_buckets = RenderBuckets::new();
+        let mut result_buffer = [0; 128];
+        test.render_get_result_commands(&mut render_buckets, &mut result_buffer[..]);
+        let commands = &render_buckets.custom_commands[Layer::AboveText as usize];
+        test.contains(
+            commands,
+            1,
+            OutputMessage::RenderString(RenderStringMsg {
+                text: "division by zero".to_owned(),
+                row: canvas_y(0),
+                column: LEFT_GUTTER_MIN_WIDTH,
+            }),
+        );
+    }
+
+    #[test]
+    fn scroll_dragging_limit() {
+        let test = create_app2(35);
+        test.repeated_paste("1\n", 39);
+        test.render();
+        test.click(test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH, 0);
+
+        assert_eq!(test.get_render_data().scroll_y, 0);
+
+        for i in 0..5 {
+            test.handle_drag(
+                test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH,
+                1 + i,
+            );
+            assert_eq!(test.get_render_data().scroll_y, 1 + i as usize);
+        }
+        test.handle_drag(test.get_render_data().result_gutter_x - SCROLL_BAR_WIDTH, 6);
+        // the scrollbar reached its bottom position, it won't go further down
+        assert_eq!(test.get_render_data().scroll_y, 5);
+    }
+
+    #[test]
     fn scroll_dragging_upwards() {
         let test = create_app2(35);
         test.repeated_paste("1\n", 39);
@@ -8498,6 +9100,121 @@ sum",
         }
     }
 
+    #[test]
+    fn test_aggregate_variables() {
+        for (text, expected) in &[
+            ("2\n4\n6\navg", &["2", "4", "6", "4"][..]),
+            ("2\n4\n6\ncount", &["2", "4", "6", "3"][..]),
+            ("2\n4\n6\nmin", &["2", "4", "6", "2"][..]),
+            ("2\n4\n6\nmax", &["2", "4", "6", "6"][..]),
+            ("2\n4\n6\nprod", &["2", "4", "6", "48"][..]),
+            (
+                "1\n--\n3 m\n250 cm\nmax",
+                &["1", "", "3 m", "250 cm", "3 m"][..],
+            ),
+            (
+                "1\n--\n3 m\n250 cm\nmin",
+                &["1", "", "3 m", "250 cm", "250 cm"][..],
+            ),
+            ("3 m\n2\nmax", &["3 m", "2", "Err"][..]),
+            ("1\n--\n3\n5\nmin * 2", &["1", "", "3", "5", "6"][..]),
+            ("max = 10\nmax", &["10", "10"][..]),
+            ("2\n5 min", &["2", "5 min"][..]),
+        ] {
+            let test = create_app2(35);
+            test.paste(text);
+            let mut result_buffer = [0; 128];
+            test.render_get_result_buf(&mut result_buffer[..]);
+            assert_results(expected, &result_buffer);
+        }
+    }
+
+    #[test]
+    fn test_prev_result_reference() {
+        let test = create_app2(35);
+        test.paste("10\nprev + 5\n\nsubtotal\nans * 2");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["10", "15", "", "", "30"][..], &result_buffer);
+
+        // modifying the referenced line recalculates the chain
+        test.set_cursor_row_col(0, 2);
+        test.input(EditorInputEvent::Char('0'), InputModifiers::none());
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["100", "105", "", "", "210"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_prev_result_reference_after_line_swap_and_removal() {
+        let test = create_app2(35);
+        test.paste("1\n2\nprev * 10");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["1", "2", "20"][..], &result_buffer);
+
+        test.set_cursor_row_col(1, 0);
+        test.input(EditorInputEvent::Up, InputModifiers::ctrl_shift());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["2", "1", "10"][..], &result_buffer);
+
+        // clearing the referenced line makes `prev` point further up
+        test.set_cursor_row_col(1, 1);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["2", "", "20"][..], &result_buffer);
+
+        // inserting a line with a result in between
+        test.input(EditorInputEvent::Char('3'), InputModifiers::none());
+        test.input(EditorInputEvent::Enter, InputModifiers::none());
+        test.input(EditorInputEvent::Char('4'), InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["2", "3", "4", "40"][..], &result_buffer);
+
+        // removing it
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["2", "3", "30"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_prev_result_reference_after_deleting_and_pasting_lines() {
+        let test = create_app2(35);
+        test.paste("5\n1\n2\nabc\n\nprev * 10");
+        test.set_cursor_row_col(2, 0);
+        test.input(EditorInputEvent::Up, InputModifiers::ctrl_shift());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["5", "2", "1", "", "", "10"][..], &result_buffer);
+        // deleting the lines with results
+        test.set_cursor_row_col(1, 0);
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        test.input(EditorInputEvent::Del, InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["5", "", "", "50"][..], &result_buffer);
+        // pasting a line with a result back
+        test.set_cursor_row_col(1, 0);
+        test.paste("7\n");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["5", "7", "", "", "70"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_explicit_unit_conversion_is_not_simplified() {
+        let test = create_app2(35);
+        test.paste("100 N in kg*m/s^2\n10 W / 2 A");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["100 (kg m) / s^2", "5 V"][..], &result_buffer);
+    }
+
     #[test]
     fn test_paste_long_text() {
         let test = create_app2(35);
@@ -10078,7 +10795,8 @@ monthly payment = r/(1 - (1+r)^(-n)) * finance amount",
     #[test]
     fn test_if_number_is_too_big_for_binary_repr_show_err() {
         let test = create_app2(35);
-        test.paste("10e24");
+        // more than MAX_INT_BITS
+        test.paste("(1 << 255) * 2");
         test.input(EditorInputEvent::Left, InputModifiers::alt());
         let mut result_buffer = [0; 128];
         test.render_get_result_buf(&mut result_buffer[..]);
@@ -10087,9 +10805,311 @@ monthly payment = r/(1 - (1+r)^(-n)) * finance amount",
     }
 
     #[test]
-    fn test_if_number_is_too_big_for_hex_repr_show_err() {
+    fn test_non_integer_in_binary_repr_show_err() {
+        let test = create_app2(35);
+        test.paste("10e24 + 0.5");
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["Err"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_complex_numbers_in_polar_repr() {
+        let test = create_app2(35);
+        test.paste("3 + 4i\n(3 + 4i) ohm\n12");
+        test.set_cursor_row_col(0, 0);
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        // Dec -> Polar for the complex numbers, Dec -> Hex for 12
+        test.input(EditorInputEvent::Right, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(
+            &["5 @ 53.1301 deg", "5 ohm @ 53.1301 deg", "C"][..],
+            &result_buffer,
+        );
+    }
+
+    #[test]
+    fn test_fractions_repr() {
+        let test = create_app2(35);
+        test.paste("2.375 in\n1/3 + 1/6\n1/3\n-7/4");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Dec -> Fraction for the quantity, Dec -> Bin for the numbers
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+        test.set_cursor_row_col(1, 0);
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Bin -> Fraction
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["2 3/8 in", "1/2", "1/3", "-1 3/4"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_durations_repr() {
+        let test = create_app2(35);
+        test.paste("9:15 - 17:40 - 0:45\n1:30:15\n1d 4h 30min\n12");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Dec -> Fraction -> Duration for the time quantities, Dec -> Bin -> Fraction for 12
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Left, InputModifiers::alt());
+        }
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(
+            &["7:40", "1:30:15", "1d 4h 30min", "12"][..],
+            &result_buffer,
+        );
+    }
+
+    #[test]
+    fn test_result_format_cycle_skips_formats_which_do_not_apply() {
+        let test = create_app2(35);
+        test.paste("1 < 2\n5 m\n12\n");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+
+        let format_of = |row: usize| test.app().editor_content.get_data(row).result_format;
+        assert_eq!(format_of(0), ResultFormat::Dec);
+        assert_eq!(format_of(1), ResultFormat::Fraction);
+        assert_eq!(format_of(2), ResultFormat::Bin);
+        // the result of an empty line is not known yet
+        assert_eq!(format_of(3), ResultFormat::Bin);
+    }
+
+    #[test]
+    fn test_octal_repr() {
+        let test = create_app2(35);
+        test.paste("493\n0o17\n255 to base 36");
+        test.set_cursor_row_col(0, 0);
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        // Dec -> Hex -> Oct
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Right, InputModifiers::alt());
+        }
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["755", "17", "73"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_scientific_repr() {
+        let test = create_app2(35);
+        test.paste("0.00012 m\n1.2e-9\n123456789\n0");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Dec -> Fraction -> Scientific for the quantity, Dec -> Bin -> Fraction for the numbers
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Left, InputModifiers::alt());
+        }
+        test.set_cursor_row_col(1, 0);
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Fraction -> Scientific, Duration is skipped for dimensionless results
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["1.2e-4 m", "1.2e-9", "1.2346e8", "0"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_engineering_repr() {
+        let test = create_app2(35);
+        test.paste("0.00012 m\n1.2e-8\n123456789");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        // Dec -> Engineering for the quantity, Dec -> Hex for the numbers
+        test.input(EditorInputEvent::Right, InputModifiers::alt());
+        test.set_cursor_row_col(1, 0);
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        // Hex -> Oct -> Engineering, Polar is skipped for real numbers
+        for _ in 0..2 {
+            test.input(EditorInputEvent::Right, InputModifiers::alt());
+        }
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["120 um", "12e-9", "123.4568e6"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_precision_suffix() {
+        let test = create_app2(35);
+        test.paste("1/3 @2dp\n123456 @3sf\n0.000123456 @2sf\n2/3\n1/3 @0dp\n1/3@2dp");
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(
+            &["0.33", "123 000", "0.00012", "0.6667", "0", "0.3333"][..],
+            &result_buffer,
+        );
+    }
+
+    #[test]
+    fn test_precision_shortcut() {
+        let test = create_app2(35);
+        test.paste("1/3\n2/3 @2dp");
+        test.set_cursor_row_col(0, 0);
+        test.input(EditorInputEvent::Char('.'), InputModifiers::alt());
+        assert_eq!("1/3 @5dp\n2/3 @2dp", test.get_editor_content());
+
+        test.input(EditorInputEvent::Down, InputModifiers::shift());
+        test.input(EditorInputEvent::Char(','), InputModifiers::alt());
+        test.input(EditorInputEvent::Char(','), InputModifiers::alt());
+        assert_eq!("1/3 @3dp\n2/3 @0dp", test.get_editor_content());
+
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(&["0.333", "1"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_fixed_width_hex_repr() {
+        let test = create_app2(35);
+        test.paste("-1 as i8\n-2 as i16\nNOT(0) as u32\n5 as u8");
+        test.set_cursor_row_col(0, 0);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Down, InputModifiers::shift());
+        }
+        test.input(EditorInputEvent::Right, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["FF", "FF FE", "FF FF FF FF", "05"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_exchange_rates() {
+        let test = create_app2(35);
+        test.paste(
+            "10 EUR in USD\n1 EUR = 1.25 USD\n10 EUR in USD\n25 USD in EUR\n10 EUR + 5 USD\n10 EUR in CZK\n1 USD = 20 CZK\n10 EUR in CZK",
+        );
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(
+            &[
+                "Err", "", "12.5 USD", "20 EUR", "14 EUR", "Err", "", "250 CZK",
+            ][..],
+            &result_buffer,
+        );
+
+        // the lines below are recalculated with the new rate
+        test.set_cursor_row_col(1, 11);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(
+            &[
+                "Err",
+                "",
+                "15 USD",
+                "16.6667 EUR",
+                "13.3333 EUR",
+                "Err",
+                "",
+                "300 CZK",
+            ][..],
+            &result_buffer,
+        );
+    }
+
+    #[test]
+    fn test_user_defined_units() {
+        let test = create_app2(35);
+        test.paste(
+            "3 sprint\nunit sprint = 2 week\n3 sprint in day\nunit story_point = 4 hour\n12 story_point in day\nunit m = 5\n1 sprint + 1 week",
+        );
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+        assert_results(
+            &["3", "", "42 day", "", "2 day", "Err", "1.5 sprint"][..],
+            &result_buffer,
+        );
+    }
+
+    #[test]
+    fn test_error_message_of_invalid_unit_definitions() {
+        let test = create_app2(35);
+        test.paste("unit m = 5\nunit nothing = 0");
+        let error_msg = |text: &str, row: isize| {
+            OutputMessage::RenderString(RenderStringMsg {
+                text: text.to_owned(),
+                row: canvas_y(row),
+                column: LEFT_GUTTER_MIN_WIDTH,
+            })
+        };
+
+        let mut render_buckets = RenderBuckets::new();
+        let mut result_buffer = [0; 128];
+        test.render_get_result_commands(&mut render_buckets, &mut result_buffer[..]);
+        let commands = &render_buckets.custom_commands[Layer::AboveText as usize];
+        test.contains(commands, 1, error_msg("a unit can't be zero", 0));
+
+        test.input(EditorInputEvent::Up, InputModifiers::none());
+        let mut render_buckets = RenderBuckets::new();
+        let mut result_buffer = [0; 128];
+        test.render_get_result_commands(&mut render_buckets, &mut result_buffer[..]);
+        let commands = &render_buckets.custom_commands[Layer::AboveText as usize];
+        // it is shown below the first line
+        test.contains(
+            commands,
+            1,
+            error_msg("a builtin unit has the same name", 1),
+        );
+    }
+
+    #[test]
+    fn test_big_numbers_in_binary_repr() {
         let test = create_app2(35);
         test.paste("10e24");
+        test.input(EditorInputEvent::Left, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["1000 01000101 10010101 00010110 00010100 00000001 01001000 01001010 00000000 00000000 00000000"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_if_number_is_too_big_for_hex_repr_show_err() {
+        let test = create_app2(35);
+        // more than MAX_INT_BITS
+        test.paste("(1 << 255) * 2");
+        test.input(EditorInputEvent::Right, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["Err"][..], &result_buffer);
+    }
+
+    #[test]
+    fn test_non_integer_in_hex_repr_show_err() {
+        let test = create_app2(35);
+        test.paste("10e24 + 0.5");
         test.input(EditorInputEvent::Right, InputModifiers::alt());
         let mut result_buffer = [0; 128];
         test.render_get_result_buf(&mut result_buffer[..]);
@@ -10097,6 +11117,17 @@ monthly payment = r/(1 - (1+r)^(-n)) * finance amount",
         assert_results(&["Err"][..], &result_buffer);
     }
 
+    #[test]
+    fn test_big_numbers_in_hex_repr() {
+        let test = create_app2(35);
+        test.paste("10e24");
+        test.input(EditorInputEvent::Right, InputModifiers::alt());
+        let mut result_buffer = [0; 128];
+        test.render_get_result_buf(&mut result_buffer[..]);
+
+        assert_results(&["8 45 95 16 14 01 48 4A 00 00 00"][..], &result_buffer);
+    }
+
     #[test]
     fn integration_test_for_rich_copy() {
         let test = create_app2(35);
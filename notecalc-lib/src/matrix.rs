@@ -1,5 +1,8 @@
-use crate::calc::{divide_op, multiply_op, CalcResult, CalcResultType};
+use crate::calc::{
+    add_op, dec, divide_op, multiply_op, sub_op, unary_minus_op, CalcResult, CalcResultType,
+};
 use crate::MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT;
+use rust_decimal::Decimal;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MatrixData {
@@ -40,8 +43,43 @@ impl MatrixData {
         self.cells.len() == 1
     }
 
-    pub fn neg(&self) -> MatrixData {
-        todo!()
+    pub fn neg(&self) -> Option<MatrixData> {
+        let cells: Option<Vec<CalcResult>> = self.cells.iter().map(unary_minus_op).collect();
+        cells.map(|it| MatrixData::new(it, self.row_count, self.col_count))
+    }
+
+    /// applies `f` on each cell, e.g. [1, 2] + 3
+    pub fn map_cells(&self, f: impl Fn(&CalcResult) -> Option<CalcResult>) -> Option<CalcResult> {
+        let cells: Option<Vec<CalcResult>> = self.cells.iter().map(f).collect();
+        cells.map(|it| {
+            CalcResult::new(
+                CalcResultType::Matrix(MatrixData::new(it, self.row_count, self.col_count)),
+                0,
+            )
+        })
+    }
+
+    /// applies `f` on the corresponding cells of the two matrices, e.g. [1, 2] + [3, 4]
+    pub fn zip_cells(
+        &self,
+        other: &MatrixData,
+        f: fn(&CalcResult, &CalcResult) -> Option<CalcResult>,
+    ) -> Option<CalcResult> {
+        if self.row_count != other.row_count || self.col_count != other.col_count {
+            return None;
+        }
+        let cells: Option<Vec<CalcResult>> = self
+            .cells
+            .iter()
+            .zip(other.cells.iter())
+            .map(|(a, b)| f(a, b))
+            .collect();
+        cells.map(|it| {
+            CalcResult::new(
+                CalcResultType::Matrix(MatrixData::new(it, self.row_count, self.col_count)),
+                0,
+            )
+        })
     }
 
     pub fn mult_scalar(&self, scalar: &CalcResult) -> Option<CalcResult> {
@@ -90,4 +128,153 @@ impl MatrixData {
 
         return result;
     }
+
+    pub fn identity(n: usize) -> MatrixData {
+        let mut cells = Vec::with_capacity(n * n);
+        for row_i in 0..n {
+            for col_i in 0..n {
+                let value = if row_i == col_i { 1 } else { 0 };
+                cells.push(CalcResult::new(CalcResultType::Number(dec(value)), 0));
+            }
+        }
+        return MatrixData::new(cells, n, n);
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.row_count == self.col_count
+    }
+
+    pub fn trace(&self) -> Option<CalcResult> {
+        if !self.is_square() {
+            return None;
+        }
+        let mut sum = self.cell(0, 0).clone();
+        for i in 1..self.row_count {
+            sum = add_op(&sum, self.cell(i, i))?;
+        }
+        return Some(sum);
+    }
+
+    pub fn det(&self) -> Option<CalcResult> {
+        if !self.is_square() {
+            return None;
+        }
+        let mut rows = self.rows();
+        let (rank, negated) = eliminate(&mut rows, self.col_count, false)?;
+        let mut det = rows[0][0].clone();
+        for (i, row) in rows.iter().enumerate().skip(1) {
+            det = multiply_op(&det, &row[i])?;
+        }
+        return if rank < self.row_count {
+            // keep the unit of the result
+            multiply_op(&det, &CalcResult::new(CalcResultType::Number(dec(0)), 0))
+        } else if negated {
+            unary_minus_op(&det)
+        } else {
+            Some(det)
+        };
+    }
+
+    pub fn rank(&self) -> Option<usize> {
+        let mut rows = self.rows();
+        eliminate(&mut rows, self.col_count, false).map(|(rank, _)| rank)
+    }
+
+    /// None if the matrix is singular
+    pub fn inverse(&self) -> Option<MatrixData> {
+        if !self.is_square() {
+            return None;
+        }
+        let identity = MatrixData::identity(self.row_count);
+        return self.solve(&identity);
+    }
+
+    /// Solves the `self * x = b` system
+    pub fn solve(&self, b: &MatrixData) -> Option<MatrixData> {
+        if !self.is_square() || self.row_count != b.row_count {
+            return None;
+        }
+        let n = self.row_count;
+        let mut rows = self.rows();
+        for (row, b_row) in rows.iter_mut().zip(b.rows().into_iter()) {
+            row.extend(b_row);
+        }
+        let (rank, _) = eliminate(&mut rows, n, true)?;
+        if rank < n {
+            return None;
+        }
+        let mut cells = Vec::with_capacity(n * b.col_count);
+        for row in rows.into_iter() {
+            cells.extend(row.into_iter().skip(n));
+        }
+        return Some(MatrixData::new(cells, n, b.col_count));
+    }
+
+    fn rows(&self) -> Vec<Vec<CalcResult>> {
+        self.cells
+            .chunks(self.col_count)
+            .map(|row| row.to_vec())
+            .collect()
+    }
+}
+
+fn abs_value_of(cell: &CalcResult) -> Option<Decimal> {
+    match &cell.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => Some(num.abs()),
+        _ => None,
+    }
+}
+
+/// Gaussian elimination with partial pivoting on the first `col_count` columns,
+/// the rest of the columns (e.g. the right hand side of a linear system) are transformed with them.
+/// If `reduced` is true, the result is in reduced row echelon form.
+/// Returns the number of pivots and whether the rows were swapped odd times.
+fn eliminate(
+    rows: &mut [Vec<CalcResult>],
+    col_count: usize,
+    reduced: bool,
+) -> Option<(usize, bool)> {
+    // rounding errors of the previous steps are not considered as pivots
+    let epsilon = Decimal::new(1, 20);
+    let mut pivot_row = 0;
+    let mut negated = false;
+    for col in 0..col_count {
+        if pivot_row >= rows.len() {
+            break;
+        }
+        let mut best: Option<(usize, Decimal)> = None;
+        for (row_i, row) in rows.iter().enumerate().skip(pivot_row) {
+            let value = abs_value_of(&row[col])?;
+            if value > epsilon && best.as_ref().map(|(_, b)| value > *b).unwrap_or(true) {
+                best = Some((row_i, value));
+            }
+        }
+        let best_row = if let Some((best_row, _)) = best {
+            best_row
+        } else {
+            continue;
+        };
+        if best_row != pivot_row {
+            rows.swap(best_row, pivot_row);
+            negated = !negated;
+        }
+        if reduced {
+            let pivot = rows[pivot_row][col].clone();
+            for cell in rows[pivot_row].iter_mut() {
+                *cell = divide_op(cell, &pivot)?;
+            }
+        }
+        for row_i in 0..rows.len() {
+            if row_i == pivot_row || (!reduced && row_i < pivot_row) {
+                continue;
+            }
+            let factor = divide_op(&rows[row_i][col], &rows[pivot_row][col])?;
+            for col_i in 0..rows[row_i].len() {
+                let d = multiply_op(&factor, &rows[pivot_row][col_i])?;
+                rows[row_i][col_i] = sub_op(&rows[row_i][col_i], &d)?;
+            }
+        }
+        pivot_row += 1;
+    }
+    return Some((pivot_row, negated));
 }
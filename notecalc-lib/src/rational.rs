@@ -0,0 +1,156 @@
+use num_bigint::BigInt;
+use rust_decimal::prelude::*;
+use std::str::FromStr;
+
+/// Exact result of divisions like 1/3.
+/// The numerator and denominator are relative primes, and the denominator is always positive.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rational {
+    pub numer: BigInt,
+    pub denom: BigInt,
+}
+
+impl Rational {
+    pub fn new(numer: BigInt, denom: BigInt) -> Option<Rational> {
+        let zero = BigInt::from(0);
+        if denom == zero {
+            return None;
+        }
+        let divisor = gcd(numer.clone(), denom.clone());
+        let (numer, denom) = (numer / &divisor, denom / &divisor);
+        Some(if denom < zero {
+            Rational {
+                numer: -numer,
+                denom: -denom,
+            }
+        } else {
+            Rational { numer, denom }
+        })
+    }
+
+    /// e.g. 1.25 is 125/100
+    pub fn from_decimal(num: &Decimal) -> Option<Rational> {
+        let numer = BigInt::from_str(&num.to_string().replace('.', "")).ok()?;
+        Rational::new(numer, pow10(num.scale()))
+    }
+
+    pub fn to_decimal(&self) -> Option<Decimal> {
+        // the integer part must fit into Decimal, the remainder is less than the denominator
+        let int_part = Decimal::from_str(&(&self.numer / &self.denom).to_string()).ok()?;
+        let rem = &self.numer % &self.denom;
+        // Decimal can't store more than 96 bits anyway, so drop the least significant ones
+        let shift = self.denom.bits().saturating_sub(96) as usize;
+        let rem = Decimal::from_str(&(rem >> shift).to_string()).ok()?;
+        let denom = Decimal::from_str(&(self.denom.clone() >> shift).to_string()).ok()?;
+        int_part.checked_add(&rem.checked_div(&denom)?)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denom == BigInt::from(1)
+    }
+
+    pub fn checked_add(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(
+            &self.numer * &other.denom + &other.numer * &self.denom,
+            &self.denom * &other.denom,
+        )
+    }
+
+    pub fn checked_sub(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(
+            &self.numer * &other.denom - &other.numer * &self.denom,
+            &self.denom * &other.denom,
+        )
+    }
+
+    pub fn checked_mul(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(&self.numer * &other.numer, &self.denom * &other.denom)
+    }
+
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(&self.numer * &other.denom, &self.denom * &other.numer)
+    }
+
+    pub fn neg(&self) -> Rational {
+        Rational {
+            numer: -self.numer.clone(),
+            denom: self.denom.clone(),
+        }
+    }
+
+    pub fn pow(&self, exp: i64) -> Option<Rational> {
+        // the result would be converted to Decimal anyway above 96 bits
+        if exp.abs() > 96 {
+            return None;
+        }
+        let mut numer = BigInt::from(1);
+        let mut denom = BigInt::from(1);
+        for _ in 0..exp.abs() {
+            numer = numer * &self.numer;
+            denom = denom * &self.denom;
+        }
+        if exp < 0 {
+            Rational::new(denom, numer)
+        } else {
+            Rational::new(numer, denom)
+        }
+    }
+
+    /// The closest fraction whose denominator is not greater than `max_denom`,
+    /// e.g. 0.3333333333 is 1/3
+    pub fn approximate(&self, max_denom: &BigInt) -> Rational {
+        if self.denom <= *max_denom {
+            return self.clone();
+        }
+        let negative = self.numer < BigInt::from(0);
+        let mut n = if negative {
+            -self.numer.clone()
+        } else {
+            self.numer.clone()
+        };
+        let mut d = self.denom.clone();
+        // convergents of the continued fraction
+        let (mut h0, mut h1) = (BigInt::from(0), BigInt::from(1));
+        let (mut k0, mut k1) = (BigInt::from(1), BigInt::from(0));
+        loop {
+            let a = &n / &d;
+            let k2 = &a * &k1 + &k0;
+            if k2 > *max_denom {
+                break;
+            }
+            let h2 = &a * &h1 + &h0;
+            h0 = h1;
+            h1 = h2;
+            k0 = k1;
+            k1 = k2;
+            let rem = n - &a * &d;
+            if rem == BigInt::from(0) {
+                break;
+            }
+            n = d;
+            d = rem;
+        }
+        Rational {
+            numer: if negative { -h1 } else { h1 },
+            denom: k1,
+        }
+    }
+}
+
+fn gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+    let zero = BigInt::from(0);
+    while b != zero {
+        let rem = &a % &b;
+        a = b;
+        b = rem;
+    }
+    if a < zero {
+        -a
+    } else {
+        a
+    }
+}
+
+fn pow10(exp: u32) -> BigInt {
+    (0..exp).fold(BigInt::from(1), |acc, _| acc * BigInt::from(10))
+}
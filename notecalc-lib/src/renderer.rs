@@ -1,18 +1,21 @@
-use crate::calc::{CalcResult, CalcResultType};
-use crate::units::units::Units;
-use crate::{ResultFormat, ResultLengths};
+use crate::calc::{complex_abs, to_big_int, CalcResult, CalcResultType};
+use crate::rational::Rational;
+use crate::units::units::{UnitOutput, Units};
+use crate::{ResultFormat, ResultLengths, ResultPrecision};
 use byteorder::WriteBytesExt;
+use num_bigint::BigInt;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 use smallvec::SmallVec;
 use std::io::Cursor;
+use std::str::FromStr;
 
 pub fn render_result(
     units: &Units,
     result: &CalcResult,
     format: &ResultFormat,
     there_was_unit_conversion: bool,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
     use_grouping: bool,
 ) -> String {
     let mut c = Cursor::new(Vec::with_capacity(64));
@@ -22,7 +25,7 @@ pub fn render_result(
         format,
         there_was_unit_conversion,
         &mut c,
-        decimal_count,
+        precision,
         use_grouping,
     );
     return unsafe { String::from_utf8_unchecked(c.into_inner()) };
@@ -34,10 +37,46 @@ pub fn render_result_into(
     format: &ResultFormat,
     there_was_unit_conversion: bool,
     f: &mut impl std::io::Write,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
     use_grouping: bool,
 ) -> ResultLengths {
+    let there_was_unit_conversion = there_was_unit_conversion || result.is_unit_converted();
+    if let Some(radix) = result.get_radix() {
+        if let Some(num) = to_big_int(&result.typ) {
+            return radix_to_string(f, &num, radix, use_grouping);
+        }
+    }
+    if let Some(int_type) = result.get_int_type() {
+        let num = to_big_int(&result.typ).filter(|_| {
+            matches!(
+                format,
+                ResultFormat::Bin | ResultFormat::Oct | ResultFormat::Hex
+            )
+        });
+        if let Some(num) = num {
+            return fixed_width_int_to_string(
+                f,
+                &int_type.to_unsigned(&num),
+                int_type.bits,
+                format,
+                use_grouping,
+            );
+        }
+    }
     match &result.typ {
+        CalcResultType::Quantity(num, unit)
+            if *format == ResultFormat::Duration
+                && unit.dimensions == units.parse(&['s']).0.dimensions =>
+        {
+            duration_to_string(f, num).unwrap_or_else(|| {
+                f.write_all(b"Err").expect("");
+                ResultLengths {
+                    int_part_len: 3,
+                    frac_part_len: 0,
+                    unit_part_len: 0,
+                }
+            })
+        }
         CalcResultType::Quantity(num, unit) => {
             let final_unit = if there_was_unit_conversion {
                 None
@@ -45,18 +84,32 @@ pub fn render_result_into(
                 unit.simplify(units)
             };
             let unit = final_unit.as_ref().unwrap_or(unit);
+            // e.g. 2 3/8 in
+            let num_format = if matches!(
+                format,
+                ResultFormat::Fraction | ResultFormat::Scientific | ResultFormat::Engineering
+            ) {
+                format
+            } else {
+                &ResultFormat::Dec
+            };
+            let prefixed_unit = if *format == ResultFormat::Engineering {
+                si_prefixed_unit(unit, num)
+            } else {
+                None
+            };
+            let (unit, num_format) = if let Some(prefixed_unit) = prefixed_unit.as_ref() {
+                (prefixed_unit, &ResultFormat::Dec)
+            } else {
+                (unit, num_format)
+            };
             if unit.units.is_empty() {
-                num_to_string(f, &num, &ResultFormat::Dec, decimal_count, use_grouping)
+                num_to_string(f, &num, num_format, precision, use_grouping)
             } else {
                 let denormalized_num = unit.from_base_to_this_unit(num);
                 if let Some(denormalized_num) = denormalized_num {
-                    let mut lens = num_to_string(
-                        f,
-                        &denormalized_num,
-                        &ResultFormat::Dec,
-                        decimal_count,
-                        use_grouping,
-                    );
+                    let mut lens =
+                        num_to_string(f, &denormalized_num, num_format, precision, use_grouping);
                     f.write_u8(b' ').expect("");
                     // TODO:mem to_string -> into(buf)
                     // implement a into(std::io:Write) method for UnitOutput
@@ -90,15 +143,84 @@ pub fn render_result_into(
         }
         CalcResultType::Number(num) => {
             // TODO optimize
-            num_to_string(f, num, format, decimal_count, use_grouping)
+            num_to_string(f, num, format, precision, use_grouping)
         }
         CalcResultType::Percentage(num) => {
-            let mut lens = num_to_string(f, num, &ResultFormat::Dec, decimal_count, use_grouping);
+            let mut lens = num_to_string(f, num, &ResultFormat::Dec, precision, use_grouping);
             f.write_u8(b' ').expect("");
             f.write_u8(b'%').expect("");
             lens.unit_part_len += 1;
             lens
         }
+        CalcResultType::BigInt(num)
+            if matches!(format, ResultFormat::Scientific | ResultFormat::Engineering) =>
+        {
+            exponent_to_string(f, &num.to_string(), format, precision)
+        }
+        CalcResultType::BigInt(num) => big_int_to_string(f, num, format, use_grouping),
+        CalcResultType::Rational(r) => {
+            if let Some(fraction) = as_fraction(r).filter(|_| *format == ResultFormat::Fraction) {
+                fraction_to_string(f, &fraction)
+            } else if let Some(num) = r.to_decimal() {
+                num_to_string(f, &num, format, precision, use_grouping)
+            } else {
+                f.write_all(b"Err").expect("");
+                ResultLengths {
+                    int_part_len: 3,
+                    frac_part_len: 0,
+                    unit_part_len: 0,
+                }
+            }
+        }
+        CalcResultType::Complex(re, im, unit) => {
+            let final_unit = if there_was_unit_conversion || unit.units.is_empty() {
+                None
+            } else {
+                unit.simplify(units)
+            };
+            let unit = final_unit.as_ref().unwrap_or(unit);
+            complex_to_string(f, re, im, unit, format, precision, use_grouping).unwrap_or_else(
+                || {
+                    f.write_all(b"Err").expect("");
+                    ResultLengths {
+                        int_part_len: 3,
+                        frac_part_len: 0,
+                        unit_part_len: 0,
+                    }
+                },
+            )
+        }
+        CalcResultType::Date(date) => {
+            let (year, month, day) = date.ymd();
+            let str = if date.has_time {
+                match date.hms() {
+                    (hour, min, 0) => {
+                        format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, min)
+                    }
+                    (hour, min, sec) => format!(
+                        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                        year, month, day, hour, min, sec
+                    ),
+                }
+            } else {
+                format!("{:04}-{:02}-{:02}", year, month, day)
+            };
+            f.write_all(str.as_bytes()).expect("");
+            ResultLengths {
+                int_part_len: str.len(),
+                frac_part_len: 0,
+                unit_part_len: 0,
+            }
+        }
+        CalcResultType::Boolean(b) => {
+            let str: &[u8] = if *b { b"true" } else { b"false" };
+            f.write_all(str).expect("");
+            ResultLengths {
+                int_part_len: str.len(),
+                frac_part_len: 0,
+                unit_part_len: 0,
+            }
+        }
         CalcResultType::Matrix(mat) => {
             f.write_u8(b'[').expect("");
             for row_i in 0..mat.row_count {
@@ -112,7 +234,7 @@ pub fn render_result_into(
                         f.write_u8(b' ').expect("");
                     }
                     let cell = &mat.cells[row_i * mat.col_count + col_i];
-                    render_result_into(units, cell, format, false, f, decimal_count, use_grouping);
+                    render_result_into(units, cell, format, false, f, precision, use_grouping);
                 }
             }
             f.write_u8(b']').expect("");
@@ -129,15 +251,21 @@ fn num_to_string(
     f: &mut impl std::io::Write,
     num: &Decimal,
     format: &ResultFormat,
-    decimal_count: Option<usize>,
+    precision: Option<ResultPrecision>,
     use_grouping: bool,
 ) -> ResultLengths {
+    if *format == ResultFormat::Fraction {
+        if let Some(fraction) = Rational::from_decimal(num).and_then(|it| as_fraction(&it)) {
+            return fraction_to_string(f, &fraction);
+        }
+    }
+    if matches!(format, ResultFormat::Scientific | ResultFormat::Engineering) {
+        return exponent_to_string(f, &num.to_string(), format, precision);
+    }
     let num_a = if *format != ResultFormat::Dec && num.trunc() == *num {
         Some(num.clone())
-    } else if let Some(decimal_count) = decimal_count {
-        let mut result = num.clone();
-        result.rescale(decimal_count as u32);
-        Some(result.normalize())
+    } else if let Some(precision) = precision {
+        Some(round_to_precision(num, precision))
     } else {
         let with_scale_0 = num.trunc();
         if *num == with_scale_0 {
@@ -148,30 +276,19 @@ fn num_to_string(
     };
     let num = num_a.as_ref().unwrap_or(num);
 
-    return if *format == ResultFormat::Bin || *format == ResultFormat::Hex {
+    return if *format == ResultFormat::Bin
+        || *format == ResultFormat::Oct
+        || *format == ResultFormat::Hex
+    {
         if let Some(n) = num.to_i64() {
-            let ss = if *format == ResultFormat::Bin {
-                format!("{:b}", n)
-            } else {
-                format!("{:X}", n)
+            let ss = match format {
+                ResultFormat::Bin => format!("{:b}", n),
+                ResultFormat::Oct => format!("{:o}", n),
+                _ => format!("{:X}", n),
             };
-            ResultLengths {
-                int_part_len: apply_grouping(
-                    f,
-                    &ss,
-                    if use_grouping {
-                        if *format == ResultFormat::Bin {
-                            8
-                        } else {
-                            2
-                        }
-                    } else {
-                        std::i32::MAX as usize
-                    },
-                ),
-                frac_part_len: 0,
-                unit_part_len: 0,
-            }
+            int_to_string(f, &ss, format, use_grouping)
+        } else if let Some(n) = to_big_int(&CalcResultType::Number(num.clone())) {
+            big_int_to_string(f, &n, format, use_grouping)
         } else {
             f.write_u8(b'E').expect("");
             f.write_u8(b'r').expect("");
@@ -222,6 +339,384 @@ fn num_to_string(
     };
 }
 
+/// Larger denominators are not readable
+const MAX_FRACTION_DENOM: u64 = 10000;
+
+/// e.g. 0.3333333333333333333333333333 is 1/3, but pi is not a fraction
+fn as_fraction(num: &Rational) -> Option<Rational> {
+    let fraction = num.approximate(&BigInt::from(MAX_FRACTION_DENOM));
+    let error = fraction.checked_sub(num)?;
+    let error_numer = if error.numer < BigInt::from(0) {
+        -error.numer
+    } else {
+        error.numer
+    };
+    // Decimal divisions are inaccurate only around the 28th digit
+    let tolerance = BigInt::from(10_000_000_000u64);
+    if error_numer * &tolerance * &tolerance <= error.denom {
+        Some(fraction)
+    } else {
+        None
+    }
+}
+
+/// Mixed number form, e.g. "2 3/8"
+fn fraction_to_string(f: &mut impl std::io::Write, fraction: &Rational) -> ResultLengths {
+    let negative = fraction.numer < BigInt::from(0);
+    let numer = if negative {
+        -fraction.numer.clone()
+    } else {
+        fraction.numer.clone()
+    };
+    let whole = &numer / &fraction.denom;
+    let rem = numer - &whole * &fraction.denom;
+    let mut str = String::with_capacity(16);
+    if negative {
+        str.push('-');
+    }
+    let has_whole_part = whole != BigInt::from(0) || rem == BigInt::from(0);
+    if has_whole_part {
+        str.push_str(&whole.to_string());
+    }
+    if rem != BigInt::from(0) {
+        if has_whole_part {
+            str.push(' ');
+        }
+        str.push_str(&format!("{}/{}", rem, fraction.denom));
+    }
+    f.write_all(str.as_bytes()).expect("");
+    ResultLengths {
+        int_part_len: str.len(),
+        frac_part_len: 0,
+        unit_part_len: 0,
+    }
+}
+
+/// e.g. "7:40", "1:30:15" or "1d 4h 30min" above a day
+fn duration_to_string(f: &mut impl std::io::Write, secs: &Decimal) -> Option<ResultLengths> {
+    let total = secs.round().to_i64()?;
+    let abs = total.checked_abs()?;
+    let (days, hours, mins, secs) = (abs / 86400, abs / 3600 % 24, abs / 60 % 60, abs % 60);
+    let mut str = String::with_capacity(16);
+    if total < 0 {
+        str.push('-');
+    }
+    if days == 0 {
+        str.push_str(&format!("{}:{:02}", hours, mins));
+        if secs != 0 {
+            str.push_str(&format!(":{:02}", secs));
+        }
+    } else {
+        let parts = [(days, "d"), (hours, "h"), (mins, "min"), (secs, "s")];
+        let parts = parts
+            .iter()
+            .filter(|(value, _)| *value != 0)
+            .map(|(value, name)| format!("{}{}", value, name))
+            .collect::<Vec<_>>();
+        str.push_str(&parts.join(" "));
+    }
+    f.write_all(str.as_bytes()).expect("");
+    Some(ResultLengths {
+        int_part_len: str.len(),
+        frac_part_len: 0,
+        unit_part_len: 0,
+    })
+}
+
+/// e.g. "3 + 4i", "(3 + 4i) ohm" or "5 @ 53.1301 deg" in polar form.
+/// The angle is calculated with f64, so it is precise only to about 15 digits.
+fn complex_to_string(
+    f: &mut impl std::io::Write,
+    re: &Decimal,
+    im: &Decimal,
+    unit: &UnitOutput,
+    format: &ResultFormat,
+    precision: Option<ResultPrecision>,
+    use_grouping: bool,
+) -> Option<ResultLengths> {
+    let in_unit = |num: &Decimal| {
+        if unit.units.is_empty() {
+            Some(*num)
+        } else {
+            unit.from_base_to_this_unit(num)
+        }
+    };
+    let unit_str = if unit.units.is_empty() {
+        String::new()
+    } else {
+        unit.to_string()
+    };
+    fn write_num(
+        f: &mut impl std::io::Write,
+        num: &Decimal,
+        precision: Option<ResultPrecision>,
+        use_grouping: bool,
+    ) {
+        num_to_string(f, num, &ResultFormat::Dec, precision, use_grouping);
+    }
+    // everything before the unit, its length is measured after it was written
+    let mut num_part: Vec<u8> = Vec::with_capacity(32);
+    let unit_part = if *format == ResultFormat::Polar {
+        let abs = in_unit(&complex_abs(re, im)?)?;
+        let angle = Decimal::from_f64(im.to_f64()?.atan2(re.to_f64()?).to_degrees())?;
+        write_num(&mut num_part, &abs, precision, use_grouping);
+        if !unit_str.is_empty() {
+            num_part.push(b' ');
+            num_part.extend_from_slice(unit_str.as_bytes());
+        }
+        // '<' would be confused with the comparison
+        num_part.extend_from_slice(b" @ ");
+        write_num(&mut num_part, &angle, precision, use_grouping);
+        "deg".to_owned()
+    } else {
+        let (re, im) = (in_unit(re)?, in_unit(im)?);
+        if !unit_str.is_empty() {
+            num_part.push(b'(');
+        }
+        if !re.is_zero() {
+            write_num(&mut num_part, &re, precision, use_grouping);
+            num_part.extend_from_slice(if im.is_sign_negative() {
+                b" - "
+            } else {
+                b" + "
+            });
+            write_num(&mut num_part, &im.abs(), precision, use_grouping);
+        } else {
+            write_num(&mut num_part, &im, precision, use_grouping);
+        }
+        num_part.push(b'i');
+        if !unit_str.is_empty() {
+            num_part.push(b')');
+        }
+        unit_str
+    };
+    f.write_all(&num_part).expect("");
+    if !unit_part.is_empty() {
+        // like at quantities, the separating space is not part of the unit length
+        f.write_u8(b' ').expect("");
+        f.write_all(unit_part.as_bytes()).expect("");
+    }
+    Some(ResultLengths {
+        int_part_len: num_part.len(),
+        frac_part_len: 0,
+        unit_part_len: unit_part.len(),
+    })
+}
+
+/// e.g. 1234.5678 is 1234.57 with 2 decimal places and 1230 with 3 significant figures
+fn round_to_precision(num: &Decimal, precision: ResultPrecision) -> Decimal {
+    let mut result = num.clone();
+    let decimal_places = match precision {
+        ResultPrecision::DecimalPlaces(count) => count as i32,
+        ResultPrecision::SignificantFigures(count) => {
+            let num_str = num.to_string();
+            let (_, digits, exponent) = significant_digits(&num_str);
+            if digits.is_empty() {
+                return result;
+            }
+            count as i32 - 1 - exponent
+        }
+    };
+    if decimal_places >= 0 {
+        result.rescale(decimal_places as u32);
+    } else {
+        // the integer part has to be rounded, e.g. 123456 is 123000 with 3 significant figures
+        let unit = Decimal::from_scientific(&format!("1e{}", -decimal_places)).ok();
+        let rounded = unit.and_then(|unit| result.checked_div(&unit)?.round().checked_mul(&unit));
+        if let Some(rounded) = rounded {
+            result = rounded;
+        }
+    }
+    result.normalize()
+}
+
+/// The sign, the significant digits (with the point if it is between them)
+/// and the exponent of the first digit, e.g. "-0.0120" is ("-", "12", -2)
+fn significant_digits(num_str: &str) -> (&str, &str, i32) {
+    let (sign, num_str) = if let Some(abs) = num_str.strip_prefix('-') {
+        ("-", abs)
+    } else {
+        ("", num_str)
+    };
+    let int_part_len = num_str.find('.').unwrap_or(num_str.len());
+    let first_digit = num_str
+        .find(|it: char| it != '0' && it != '.')
+        .unwrap_or(num_str.len());
+    let exponent = if first_digit < int_part_len {
+        int_part_len as i32 - first_digit as i32 - 1
+    } else {
+        // the point is before the first digit
+        int_part_len as i32 - first_digit as i32
+    };
+    let digits = num_str[first_digit..].trim_end_matches(|it| it == '0' || it == '.');
+    (sign, digits, exponent)
+}
+
+/// e.g. 1.2e-9, or 120e-9 in engineering format where the exponent is a multiple of 3
+fn exponent_to_string(
+    f: &mut impl std::io::Write,
+    num_str: &str,
+    format: &ResultFormat,
+    precision: Option<ResultPrecision>,
+) -> ResultLengths {
+    let (sign, digits, exponent) = significant_digits(num_str);
+    let digits = digits.replace('.', "");
+    if digits.is_empty() {
+        f.write_u8(b'0').expect("");
+        return ResultLengths {
+            int_part_len: 1,
+            frac_part_len: 0,
+            unit_part_len: 0,
+        };
+    }
+    let exponent_step = if *format == ResultFormat::Engineering {
+        3
+    } else {
+        1
+    };
+    let int_digit_count = exponent.rem_euclid(exponent_step) as usize + 1;
+    let mut exponent = exponent - (int_digit_count as i32 - 1);
+    // a Decimal can't hold more digits
+    let digits = &digits[..digits.len().min(28)];
+    let mantissa = if digits.len() > int_digit_count {
+        Decimal::from_str(&format!(
+            "{}{}.{}",
+            sign,
+            &digits[..int_digit_count],
+            &digits[int_digit_count..]
+        ))
+    } else {
+        Decimal::from_str(&format!(
+            "{}{:0<width$}",
+            sign,
+            digits,
+            width = int_digit_count
+        ))
+    };
+    let mut mantissa = match mantissa {
+        Ok(mantissa) => mantissa,
+        Err(_) => {
+            f.write_all(b"Err").expect("");
+            return ResultLengths {
+                int_part_len: 3,
+                frac_part_len: 0,
+                unit_part_len: 0,
+            };
+        }
+    };
+    if let Some(precision) = precision {
+        mantissa = round_to_precision(&mantissa, precision);
+        // e.g. 9.99999 is rounded up to 10
+        let limit = Decimal::from(10i64.pow(exponent_step as u32));
+        let rounded_up = mantissa.abs() >= limit;
+        if let Some(reduced) = mantissa.checked_div(&limit).filter(|_| rounded_up) {
+            mantissa = reduced;
+            exponent += exponent_step;
+        }
+    }
+    let mantissa_str = mantissa.to_string();
+    let int_part_len = mantissa_str.find('.').unwrap_or(mantissa_str.len());
+    f.write_all(mantissa_str.as_bytes()).expect("");
+    let mut frac_part_len = mantissa_str.len() - int_part_len;
+    if exponent != 0 {
+        let exponent_str = format!("e{}", exponent);
+        f.write_all(exponent_str.as_bytes()).expect("");
+        frac_part_len += exponent_str.len();
+    }
+    ResultLengths {
+        int_part_len,
+        frac_part_len,
+        unit_part_len: 0,
+    }
+}
+
+/// e.g. 1.2e-8 m is 12 nm in engineering format
+fn si_prefixed_unit(unit: &UnitOutput, num: &Decimal) -> Option<UnitOutput> {
+    let denormalized_num = unit.from_base_to_this_unit(num)?;
+    let num_str = denormalized_num.to_string();
+    let (_, digits, exponent) = significant_digits(&num_str);
+    if digits.is_empty() {
+        return None;
+    }
+    unit.with_si_prefix(exponent - exponent.rem_euclid(3))
+}
+
+fn big_int_to_string(
+    f: &mut impl std::io::Write,
+    num: &BigInt,
+    format: &ResultFormat,
+    use_grouping: bool,
+) -> ResultLengths {
+    let ss = match format {
+        ResultFormat::Bin => format!("{:b}", num),
+        ResultFormat::Oct => format!("{:o}", num),
+        ResultFormat::Hex => format!("{:X}", num),
+        _ => num.to_string(),
+    };
+    int_to_string(f, &ss, format, use_grouping)
+}
+
+/// e.g. 255 to base 36 is "73"
+fn radix_to_string(
+    f: &mut impl std::io::Write,
+    num: &BigInt,
+    radix: u8,
+    use_grouping: bool,
+) -> ResultLengths {
+    let ss = num.to_str_radix(u32::from(radix)).to_uppercase();
+    let format = match radix {
+        2 => ResultFormat::Bin,
+        8 => ResultFormat::Oct,
+        16 => ResultFormat::Hex,
+        _ => ResultFormat::Dec,
+    };
+    int_to_string(f, &ss, &format, use_grouping)
+}
+
+/// The bit pattern padded to the full width, e.g. -1 as i16 is "FF FF"
+fn fixed_width_int_to_string(
+    f: &mut impl std::io::Write,
+    num: &BigInt,
+    bits: u8,
+    format: &ResultFormat,
+    use_grouping: bool,
+) -> ResultLengths {
+    let (radix, bits_per_digit) = match format {
+        ResultFormat::Bin => (2, 1),
+        ResultFormat::Oct => (8, 3),
+        _ => (16, 4),
+    };
+    let digit_count = (usize::from(bits) + bits_per_digit - 1) / bits_per_digit;
+    let ss = format!(
+        "{:0>width$}",
+        num.to_str_radix(radix).to_uppercase(),
+        width = digit_count
+    );
+    int_to_string(f, &ss, format, use_grouping)
+}
+
+fn int_to_string(
+    f: &mut impl std::io::Write,
+    ss: &str,
+    format: &ResultFormat,
+    use_grouping: bool,
+) -> ResultLengths {
+    let group_size = if !use_grouping {
+        std::i32::MAX as usize
+    } else {
+        match format {
+            ResultFormat::Bin => 8,
+            ResultFormat::Hex => 2,
+            _ => 3,
+        }
+    };
+    ResultLengths {
+        int_part_len: apply_grouping(f, ss, group_size),
+        frac_part_len: 0,
+        unit_part_len: 0,
+    }
+}
+
 fn apply_grouping(f: &mut impl std::io::Write, ss: &str, group_size: usize) -> usize {
     // TODO isnt it too much/is it enough?
     let mut buf: SmallVec<[u8; 128]> = SmallVec::with_capacity(ss.len());
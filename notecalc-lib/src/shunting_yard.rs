@@ -257,8 +257,17 @@ impl ShuntingYard {
             input_index += 1; // it is here so it is incremented always when "continue"
             let input_token = &tokens[input_index as usize];
             match &input_token.typ {
-                TokenType::StringLiteral => {
-                    if let Some(fn_type) = FnType::value_of(input_token.ptr) {
+                TokenType::StringLiteral | TokenType::Operator(OperatorTokenType::Fn { .. }) => {
+                    let fn_type =
+                        if let TokenType::Operator(OperatorTokenType::Fn { typ, .. }) =
+                            &input_token.typ
+                        {
+                            // user defined functions are recognized by the token parser
+                            Some(*typ)
+                        } else {
+                            FnType::value_of(input_token.ptr)
+                        };
+                    if let Some(fn_type) = fn_type {
                         // next token is parenthesis
                         if tokens
                             .get(input_index as usize + 1)
@@ -286,6 +295,9 @@ impl ShuntingYard {
                         }
                     }
 
+                    // e.g. a user defined function without parenthesis
+                    tokens[input_index as usize].typ = TokenType::StringLiteral;
+                    let input_token = &tokens[input_index as usize];
                     if !input_token.ptr[0].is_ascii_whitespace() {
                         v.had_non_ws_string_literal = true;
                     }
@@ -637,12 +649,44 @@ impl ShuntingYard {
                         )) =
                             ShuntingYard::get_next_nonstring_token(tokens, input_index as usize + 1)
                         {
-                            if ShuntingYard::get_next_nonstring_token(
+                            let next_token = ShuntingYard::get_next_nonstring_token(
                                 tokens,
                                 input_index as usize + 1 + offset + 1,
-                            )
-                            .is_some()
-                            {
+                            );
+                            let closes_fn_arg = next_token
+                                .map(|(it, _)| {
+                                    matches!(
+                                        (&it.typ, v.parenthesis_stack.last()),
+                                        (
+                                            TokenType::Operator(OperatorTokenType::ParenClose),
+                                            Some(ParenStackEntry::Fn(..))
+                                        ) | (
+                                            TokenType::Operator(OperatorTokenType::Comma),
+                                            Some(ParenStackEntry::Fn(..))
+                                        )
+                                    )
+                                })
+                                .unwrap_or(false);
+                            if closes_fn_arg && !v.expect_expression {
+                                // e.g. "ceil(3.2 hour in min)", the conversion is applied
+                                // only on the content of the parenthesis
+                                while let Some(top) = operator_stack.last() {
+                                    if top.op_type == OperatorTokenType::ParenOpen {
+                                        break;
+                                    }
+                                    let top = operator_stack.pop().unwrap();
+                                    to_out2(
+                                        output_stack,
+                                        TokenType::Operator(top.op_type),
+                                        top.index_into_tokens,
+                                    );
+                                }
+                                input_index += 1 + offset as isize;
+                                to_out2(output_stack, TokenType::Unit(unit.clone()), input_index);
+                                to_out2(output_stack, TokenType::Operator(op.clone()), input_index);
+                                v.prev_token_type = ValidationTokenType::Expr;
+                                continue;
+                            } else if next_token.is_some() {
                                 // after 'in', there must be a single unit component, nothing else
                                 continue;
                             }
@@ -669,9 +713,94 @@ impl ShuntingYard {
                             // it is not an "in" operator but a string literal
                         }
                     }
+                    OperatorTokenType::ToBase(..) => {
+                        // like 'in', it is applied on the whole expression, so it must be the last operator
+                        let is_last = ShuntingYard::get_next_nonstring_token(
+                            tokens,
+                            input_index as usize + 1,
+                        )
+                        .is_none();
+                        if v.expect_expression || !is_last {
+                            ShuntingYard::rollback(
+                                &mut operator_stack,
+                                output_stack,
+                                input_index + 1,
+                                &mut v,
+                            );
+                            continue;
+                        }
+                        v.prev_token_type = ValidationTokenType::Expr;
+                        if v.can_be_valid_closing_token() {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                            );
+                            to_out2(output_stack, TokenType::Operator(op.clone()), input_index);
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                        }
+                    }
+                    OperatorTokenType::AsInt(..) => {
+                        // postfix operator, applied on everything before it in the same parenthesis
+                        if v.expect_expression {
+                            ShuntingYard::rollback(
+                                &mut operator_stack,
+                                output_stack,
+                                input_index + 1,
+                                &mut v,
+                            );
+                            continue;
+                        }
+                        ShuntingYard::operator_rule(
+                            op,
+                            &mut operator_stack,
+                            output_stack,
+                            &mut v.last_valid_operator_index,
+                            &mut v.last_valid_output_range,
+                            input_index,
+                        );
+                        to_out2(output_stack, TokenType::Operator(op.clone()), input_index);
+                        v.prev_token_type = ValidationTokenType::Expr;
+                        if v.can_be_valid_closing_token() {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                            );
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                        }
+                    }
                     OperatorTokenType::UnaryPlus | OperatorTokenType::UnaryMinus => {
                         panic!("Token parser does not generate unary operators");
                     }
+                    OperatorTokenType::Not => {
+                        // prefix operator, e.g. "not a > b"
+                        if !v.expect_expression {
+                            ShuntingYard::rollback(
+                                &mut operator_stack,
+                                output_stack,
+                                input_index + 1,
+                                &mut v,
+                            );
+                            continue;
+                        }
+                        v.had_operator = true;
+                        v.prev_token_type = ValidationTokenType::Op;
+                        operator_stack.push(ShuntingYardOperatorResult {
+                            op_type: op.clone(),
+                            index_into_tokens: input_index,
+                        });
+                    }
                     _ => {
                         if !matches!(op, OperatorTokenType::BinNot) && v.expect_expression {
                             ShuntingYard::rollback(
@@ -722,7 +851,53 @@ impl ShuntingYard {
                         &mut input_index,
                     );
                 }
-                TokenType::Variable { .. } | TokenType::LineReference { .. } => {
+                TokenType::ImaginaryLiteral(num) => {
+                    let num = num.clone();
+                    ShuntingYard::handle_num_token(
+                        TokenType::ImaginaryLiteral(if v.neg { (&num).neg() } else { num }),
+                        &mut v,
+                        tokens,
+                        output_stack,
+                        &mut operator_stack,
+                        &mut input_index,
+                    );
+                }
+                TokenType::DurationLiteral(secs, unit) => {
+                    let typ = TokenType::DurationLiteral(*secs, unit.clone());
+                    ShuntingYard::handle_num_token(
+                        typ,
+                        &mut v,
+                        tokens,
+                        output_stack,
+                        &mut operator_stack,
+                        &mut input_index,
+                    );
+                }
+                TokenType::DateLiteral(date) => {
+                    let date = *date;
+                    ShuntingYard::handle_num_token(
+                        TokenType::DateLiteral(date),
+                        &mut v,
+                        tokens,
+                        output_stack,
+                        &mut operator_stack,
+                        &mut input_index,
+                    );
+                }
+                TokenType::BigIntLiteral(num) => {
+                    let num = num.clone();
+                    ShuntingYard::handle_num_token(
+                        TokenType::BigIntLiteral(if v.neg { -num } else { num }),
+                        &mut v,
+                        tokens,
+                        output_stack,
+                        &mut operator_stack,
+                        &mut input_index,
+                    );
+                }
+                TokenType::Variable { .. }
+                | TokenType::LineReference { .. }
+                | TokenType::LineRange { .. } => {
                     if !v.expect_expression {
                         ShuntingYard::rollback(
                             &mut operator_stack,
@@ -1007,7 +1182,7 @@ pub mod tests {
     use crate::helper::create_vars;
     use crate::token_parser::TokenParser;
     use crate::units::units::{UnitOutput, Units};
-    use crate::{Variable, Variables, MAX_LINE_COUNT};
+    use crate::{Variable, Variables, VARIABLE_COUNT};
     use bumpalo::Bump;
     use rust_decimal::prelude::*;
 
@@ -1197,13 +1372,14 @@ pub mod tests {
     }
 
     fn test_output_vars(var_names: &[&'static [char]], text: &str, expected_tokens: &[Token]) {
-        let var_names: Vec<Option<Variable>> = (0..MAX_LINE_COUNT + 1)
+        let var_names: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
             .into_iter()
             .map(|index| {
                 if let Some(var_name) = var_names.get(index) {
                     Some(Variable {
                         name: Box::from(*var_name),
                         value: Err(()),
+                        user_fn: None,
                     })
                 } else {
                     None
@@ -1246,10 +1422,12 @@ pub mod tests {
         vars[0] = Some(Variable {
             name: Box::from(&['b', '0'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            user_fn: None,
         });
         vars[1] = Some(Variable {
             name: Box::from(&['&', '[', '1', ']'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            user_fn: None,
         });
         let _ = do_shunting_yard(&temp, &units, &mut tokens, &vars, &arena);
         compare_tokens(expected_tokens, &tokens);
@@ -2174,6 +2352,39 @@ pub mod tests {
         )
     }
 
+    #[test]
+    fn test_unit_conversion_inside_fn() {
+        test_output(
+            "ceil(3.2 hour in min)",
+            &[
+                numf(3.2),
+                apply_to_prev_token_unit("hour"),
+                unit("min"),
+                op(OperatorTokenType::UnitConverter),
+                op(OperatorTokenType::Fn {
+                    arg_count: 1,
+                    typ: FnType::Ceil,
+                }),
+            ],
+        );
+        test_output(
+            "round(3.2 hour to min, 1) * 2",
+            &[
+                numf(3.2),
+                apply_to_prev_token_unit("hour"),
+                unit("min"),
+                op(OperatorTokenType::UnitConverter),
+                num(1),
+                op(OperatorTokenType::Fn {
+                    arg_count: 2,
+                    typ: FnType::Round,
+                }),
+                num(2),
+                op(OperatorTokenType::Mult),
+            ],
+        );
+    }
+
     #[test]
     fn test_fn_output() {
         test_output(
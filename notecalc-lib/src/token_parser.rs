@@ -1,7 +1,12 @@
+use crate::calc::{int_result, CalcResultType, IntType};
+use crate::datetime::{current_time, DateTime, SECONDS_PER_DAY};
 use crate::functions::FnType;
 use crate::units::units::{UnitOutput, Units};
-use crate::{Variables, SUM_VARIABLE_INDEX};
+use crate::{
+    ResultPrecision, Variables, AGGREGATE_VARIABLES, MAX_RESULT_PRECISION, SUM_VARIABLE_INDEX,
+};
 use bumpalo::Bump;
+use num_bigint::BigInt;
 use rust_decimal::prelude::*;
 use std::str::FromStr;
 
@@ -9,9 +14,26 @@ use std::str::FromStr;
 pub enum TokenType {
     StringLiteral,
     // index to the variable vec
-    Variable { var_index: usize },
-    LineReference { var_index: usize },
+    Variable {
+        var_index: usize,
+    },
+    LineReference {
+        var_index: usize,
+    },
+    /// e.g. &[3:7], the var indices of the first and last lines
+    LineRange {
+        from: usize,
+        to: usize,
+    },
     NumberLiteral(Decimal),
+    /// hex or binary literals which don't fit into a Decimal
+    BigIntLiteral(BigInt),
+    /// e.g. 4i or 4j
+    ImaginaryLiteral(Decimal),
+    /// e.g. 2026-10-18, today or now
+    DateLiteral(DateTime),
+    /// e.g. 1:30:15 or 2h 30min, the value is in seconds
+    DurationLiteral(Decimal, UnitOutput),
     Operator(OperatorTokenType),
     Unit(UnitOutput),
     NumberErr,
@@ -28,7 +50,12 @@ const PI: Decimal = Decimal::from_parts(1102470953, 185874565, 1703060790, false
 
 impl<'text_ptr> Token<'text_ptr> {
     pub fn is_number(&self) -> bool {
-        matches!(self.typ, TokenType::NumberLiteral(..))
+        matches!(
+            self.typ,
+            TokenType::NumberLiteral(..)
+                | TokenType::BigIntLiteral(..)
+                | TokenType::ImaginaryLiteral(..)
+        )
     }
 
     pub fn is_string(&self) -> bool {
@@ -58,7 +85,24 @@ pub enum OperatorTokenType {
     UnaryMinus,
     Mult,
     Div,
+    ElementwiseMult,
+    ElementwiseDiv,
     Perc,
+    PercOf,
+    PercOn,
+    PercOff,
+    IsWhatPercOf,
+    IsPercOfWhat,
+    PercChange,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
     BinAnd,
     BinOr,
     BinXor,
@@ -73,6 +117,8 @@ pub enum OperatorTokenType {
     ShiftRight,
     Assign,
     UnitConverter,
+    ToBase(u8),
+    AsInt(IntType),
     ApplyUnit(UnitOutput),
     Matrix { row_count: usize, col_count: usize },
     Fn { arg_count: usize, typ: FnType },
@@ -85,32 +131,61 @@ pub enum Assoc {
 }
 
 impl OperatorTokenType {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            OperatorTokenType::Less
+                | OperatorTokenType::LessOrEqual
+                | OperatorTokenType::Greater
+                | OperatorTokenType::GreaterOrEqual
+                | OperatorTokenType::Equal
+                | OperatorTokenType::NotEqual
+        )
+    }
+
     pub fn precedence(&self) -> usize {
         match self {
-            OperatorTokenType::Add => 2,
-            OperatorTokenType::UnaryPlus => 4,
-            OperatorTokenType::Sub => 2,
-            OperatorTokenType::UnaryMinus => 4,
-            OperatorTokenType::Mult => 3,
-            OperatorTokenType::Div => 3,
-            OperatorTokenType::Perc => 6,
+            OperatorTokenType::Or => 1,
+            OperatorTokenType::And => 2,
+            OperatorTokenType::Not => 3,
+            OperatorTokenType::Less
+            | OperatorTokenType::LessOrEqual
+            | OperatorTokenType::Greater
+            | OperatorTokenType::GreaterOrEqual
+            | OperatorTokenType::Equal
+            | OperatorTokenType::NotEqual => 4,
+            OperatorTokenType::Add => 5,
+            OperatorTokenType::UnaryPlus => 7,
+            OperatorTokenType::Sub => 5,
+            OperatorTokenType::UnaryMinus => 7,
+            OperatorTokenType::Mult => 6,
+            OperatorTokenType::Div => 6,
+            OperatorTokenType::ElementwiseMult => 6,
+            OperatorTokenType::ElementwiseDiv => 6,
+            OperatorTokenType::Perc => 9,
+            OperatorTokenType::PercOf | OperatorTokenType::PercOn | OperatorTokenType::PercOff => 6,
+            OperatorTokenType::IsWhatPercOf
+            | OperatorTokenType::IsPercOfWhat
+            | OperatorTokenType::PercChange => 0,
             OperatorTokenType::BinAnd => 0,
             OperatorTokenType::BinOr => 0,
             OperatorTokenType::BinXor => 0,
-            OperatorTokenType::BinNot => 4,
-            OperatorTokenType::Pow => 6,
+            OperatorTokenType::BinNot => 7,
+            OperatorTokenType::Pow => 9,
             OperatorTokenType::ParenOpen => 0,
             OperatorTokenType::ParenClose => 0,
             OperatorTokenType::ShiftLeft => 0,
             OperatorTokenType::ShiftRight => 0,
             OperatorTokenType::Assign => 0,
             OperatorTokenType::UnitConverter => 0,
+            OperatorTokenType::ToBase(_) => 0,
+            OperatorTokenType::AsInt(_) => 0,
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => 0,
             OperatorTokenType::BracketOpen => 0,
             OperatorTokenType::BracketClose => 0,
             OperatorTokenType::Matrix { .. } => 0,
             OperatorTokenType::Fn { .. } => 0,
-            OperatorTokenType::ApplyUnit(_) => 5,
+            OperatorTokenType::ApplyUnit(_) => 8,
         }
     }
 
@@ -123,7 +198,24 @@ impl OperatorTokenType {
             OperatorTokenType::UnaryMinus => Assoc::Left,
             OperatorTokenType::Mult => Assoc::Left,
             OperatorTokenType::Div => Assoc::Left,
+            OperatorTokenType::ElementwiseMult => Assoc::Left,
+            OperatorTokenType::ElementwiseDiv => Assoc::Left,
             OperatorTokenType::Perc => Assoc::Left,
+            OperatorTokenType::PercOf
+            | OperatorTokenType::PercOn
+            | OperatorTokenType::PercOff
+            | OperatorTokenType::IsWhatPercOf
+            | OperatorTokenType::IsPercOfWhat
+            | OperatorTokenType::PercChange => Assoc::Left,
+            OperatorTokenType::Less
+            | OperatorTokenType::LessOrEqual
+            | OperatorTokenType::Greater
+            | OperatorTokenType::GreaterOrEqual
+            | OperatorTokenType::Equal
+            | OperatorTokenType::NotEqual => Assoc::Left,
+            OperatorTokenType::And => Assoc::Left,
+            OperatorTokenType::Or => Assoc::Left,
+            OperatorTokenType::Not => Assoc::Left,
             OperatorTokenType::BinAnd => Assoc::Left,
             OperatorTokenType::BinOr => Assoc::Left,
             OperatorTokenType::BinXor => Assoc::Left,
@@ -134,6 +226,8 @@ impl OperatorTokenType {
             OperatorTokenType::ShiftRight => Assoc::Left,
             OperatorTokenType::Assign => Assoc::Left,
             OperatorTokenType::UnitConverter => Assoc::Left,
+            OperatorTokenType::ToBase(_) => Assoc::Left,
+            OperatorTokenType::AsInt(_) => Assoc::Left,
             // Right, so 1 comma won't replace an other on the operator stack
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => Assoc::Right,
             OperatorTokenType::BracketOpen => Assoc::Left,
@@ -147,6 +241,8 @@ impl OperatorTokenType {
 
 pub struct TokenParser {}
 
+const PREV_RESULT_NAMES: [&[char]; 2] = [&['p', 'r', 'e', 'v'], &['a', 'n', 's']];
+
 #[derive(Clone, Copy)]
 enum CanBeUnit {
     Not,
@@ -165,6 +261,18 @@ impl TokenParser {
     ) {
         let mut index = 0;
         let mut can_be_unit = CanBeUnit::Not;
+        // the previous non whitespace token was a '%'
+        let mut after_percentage = false;
+        // "% change from" was found, 'to' is not a unit conversion
+        let mut percentage_change = false;
+        // the previous non whitespace token was a ')'
+        let mut after_paren_close = false;
+        // the current operand contains a comparison, so it is a boolean
+        let mut after_comparison = false;
+        // the previous non whitespace token can be the left side of a binary operator
+        let mut after_operand = false;
+        // the previous non whitespace token is an operator which expects an operand
+        let mut after_operator = false;
         if line.starts_with(&['-', '-']) {
             dst.push(Token {
                 ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it)),
@@ -174,16 +282,58 @@ impl TokenParser {
             return;
         }
         while index < line.len() {
-            let parse_result = TokenParser::try_extract_variable_name(
+            let parse_result = TokenParser::try_extract_line_range(
                 &line[index..],
                 variable_names,
                 line_index,
                 allocator,
             )
             .or_else(|| {
+                TokenParser::try_extract_variable_name(
+                    &line[index..],
+                    variable_names,
+                    line_index,
+                    can_be_unit,
+                    allocator,
+                )
+            })
+            .or_else(|| {
+                TokenParser::try_extract_date_literal(&line[index..], after_operator, allocator)
+            })
+            .or_else(|| TokenParser::try_extract_duration_literal(&line[index..], units, allocator))
+            .or_else(|| {
+                TokenParser::try_extract_percentage_phrase(
+                    &line[index..],
+                    after_percentage,
+                    percentage_change,
+                    allocator,
+                )
+            })
+            .or_else(|| {
+                TokenParser::try_extract_logical_operator(
+                    &line[index..],
+                    after_comparison,
+                    allocator,
+                )
+            })
+            .or_else(|| {
+                // "(...) in" is a conversion, not an inch
+                let can_be_unit = if after_paren_close
+                    && TokenParser::starts_with_unit_converter(&line[index..], units)
+                {
+                    CanBeUnit::Not
+                } else {
+                    can_be_unit
+                };
                 TokenParser::try_extract_unit(&line[index..], units, can_be_unit, allocator)
                     .or_else(|| {
-                        TokenParser::try_extract_operator(&line[index..], allocator).or_else(|| {
+                        TokenParser::try_extract_operator(
+                            &line[index..],
+                            units,
+                            after_operand,
+                            allocator,
+                        )
+                        .or_else(|| {
                             TokenParser::try_extract_number_literal(&line[index..], allocator)
                                 .or_else(|| {
                                     TokenParser::try_extract_string_literal(
@@ -203,7 +353,10 @@ impl TokenParser {
                             can_be_unit = CanBeUnit::Not;
                         }
                     }
-                    TokenType::NumberLiteral(..) | TokenType::NumberErr => {
+                    TokenType::NumberLiteral(..)
+                    | TokenType::BigIntLiteral(..)
+                    | TokenType::ImaginaryLiteral(..)
+                    | TokenType::NumberErr => {
                         can_be_unit = CanBeUnit::ApplyToPrevToken;
                     }
                     TokenType::Unit(..) => {
@@ -221,10 +374,51 @@ impl TokenParser {
                             _ => can_be_unit = CanBeUnit::Not,
                         }
                     }
-                    TokenType::Variable { .. } | TokenType::LineReference { .. } => {
+                    TokenType::Variable { .. }
+                    | TokenType::LineReference { .. }
+                    | TokenType::LineRange { .. }
+                    | TokenType::DateLiteral(..)
+                    | TokenType::DurationLiteral(..) => {
                         can_be_unit = CanBeUnit::Not;
                     }
                 }
+                if token.is_string() && token.ptr[0] == '%' {
+                    percentage_change = true;
+                }
+                if let TokenType::Operator(typ) = &token.typ {
+                    if typ.is_comparison() {
+                        after_comparison = true;
+                    } else if matches!(
+                        typ,
+                        OperatorTokenType::And
+                            | OperatorTokenType::Or
+                            | OperatorTokenType::Comma
+                            | OperatorTokenType::Semicolon
+                            | OperatorTokenType::Assign
+                    ) {
+                        after_comparison = false;
+                    }
+                }
+                if !token.ptr[0].is_ascii_whitespace() {
+                    after_percentage =
+                        matches!(token.typ, TokenType::Operator(OperatorTokenType::Perc));
+                    after_paren_close = matches!(
+                        token.typ,
+                        TokenType::Operator(OperatorTokenType::ParenClose)
+                    );
+                    after_operand = match &token.typ {
+                        TokenType::StringLiteral => false,
+                        TokenType::Operator(typ) => matches!(
+                            typ,
+                            OperatorTokenType::ParenClose
+                                | OperatorTokenType::BracketClose
+                                | OperatorTokenType::ApplyUnit(..)
+                                | OperatorTokenType::Perc
+                        ),
+                        _ => true,
+                    };
+                    after_operator = matches!(token.typ, TokenType::Operator(..)) && !after_operand;
+                }
                 index += token.ptr.len();
                 dst.push(token);
             } else {
@@ -233,6 +427,14 @@ impl TokenParser {
         }
     }
 
+    fn int_literal(num: BigInt) -> Option<TokenType> {
+        match int_result(num)? {
+            CalcResultType::Number(num) => Some(TokenType::NumberLiteral(num)),
+            CalcResultType::BigInt(num) => Some(TokenType::BigIntLiteral(num)),
+            _ => None,
+        }
+    }
+
     pub fn try_extract_number_literal<'text_ptr>(
         str: &[char],
         allocator: &'text_ptr Bump,
@@ -267,11 +469,22 @@ impl TokenParser {
             let mut end_index_before_last_whitespace = i;
             while i < str.len() {
                 if str[i] == '0' || str[i] == '1' {
+                    if number_str_index == number_str.len() {
+                        return None;
+                    }
                     end_index_before_last_whitespace = i + 1;
                     number_str[number_str_index] = str[i] as u8;
                     number_str_index += 1;
                 } else if str[i].is_ascii_whitespace() {
                     // allowed
+                } else if str[i] == '_'
+                    && (str[i - 1] == '0' || str[i - 1] == '1')
+                    && str
+                        .get(i + 1)
+                        .map(|it| *it == '0' || *it == '1')
+                        .unwrap_or(false)
+                {
+                    // digit separator, e.g. 0b1010_0101
                 } else {
                     break;
                 }
@@ -279,14 +492,49 @@ impl TokenParser {
             }
             i = end_index_before_last_whitespace;
             if i > 2 {
-                // Decimal cannot parse binary, that's why the explicit i64 type
-                let num: i64 = i64::from_str_radix(
-                    &unsafe { std::str::from_utf8_unchecked(&number_str[0..number_str_index]) },
-                    2,
-                )
-                .ok()?;
+                // Decimal cannot parse binary
+                let num = BigInt::parse_bytes(&number_str[0..number_str_index], 2)?;
+                Some(Token {
+                    typ: TokenParser::int_literal(num)?,
+                    // ptr: &str[0..i],
+                    ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
+                    has_error: false,
+                })
+            } else {
+                None
+            }
+        } else if str[i..].starts_with(&['0', 'o']) {
+            i += 2;
+            let mut end_index_before_last_whitespace = i;
+            while i < str.len() {
+                if ('0'..='7').contains(&str[i]) {
+                    if number_str_index == number_str.len() {
+                        return None;
+                    }
+                    end_index_before_last_whitespace = i + 1;
+                    number_str[number_str_index] = str[i] as u8;
+                    number_str_index += 1;
+                } else if str[i].is_ascii_whitespace() {
+                    // allowed
+                } else if str[i] == '_'
+                    && ('0'..='7').contains(&str[i - 1])
+                    && str
+                        .get(i + 1)
+                        .map(|it| ('0'..='7').contains(it))
+                        .unwrap_or(false)
+                {
+                    // digit separator, e.g. 0o755_644
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            i = end_index_before_last_whitespace;
+            if i > 2 {
+                // Decimal cannot parse octal
+                let num = BigInt::parse_bytes(&number_str[0..number_str_index], 8)?;
                 Some(Token {
-                    typ: TokenType::NumberLiteral(num.into()),
+                    typ: TokenParser::int_literal(num)?,
                     // ptr: &str[0..i],
                     ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                     has_error: false,
@@ -308,11 +556,22 @@ impl TokenParser {
                         })
                         .unwrap_or(true)
                 {
+                    if number_str_index == number_str.len() {
+                        return None;
+                    }
                     end_index_before_last_whitespace = i + 1;
                     number_str[number_str_index] = str[i] as u8;
                     number_str_index += 1;
                 } else if str[i].is_ascii_whitespace() {
                     // allowed
+                } else if str[i] == '_'
+                    && str[i - 1].is_ascii_hexdigit()
+                    && str
+                        .get(i + 1)
+                        .map(|it| it.is_ascii_hexdigit())
+                        .unwrap_or(false)
+                {
+                    // digit separator, e.g. 0xFFFF_FFFF
                 } else {
                     break;
                 }
@@ -320,14 +579,10 @@ impl TokenParser {
             }
             i = end_index_before_last_whitespace;
             if i > 2 {
-                // Decimal cannot parse hex, that's why the explicit i64 type
-                let num: i64 = i64::from_str_radix(
-                    &unsafe { std::str::from_utf8_unchecked(&number_str[0..number_str_index]) },
-                    16,
-                )
-                .ok()?;
+                // Decimal cannot parse hex
+                let num = BigInt::parse_bytes(&number_str[0..number_str_index], 16)?;
                 Some(Token {
-                    typ: TokenType::NumberLiteral(num.into()),
+                    typ: TokenParser::int_literal(num)?,
                     // ptr: &str[0..i],
                     ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                     has_error: false,
@@ -345,21 +600,35 @@ impl TokenParser {
             let mut e_count = 0;
             let mut end_index_before_last_whitespace = 0;
             let mut e_neg = false;
+            let mut e_has_sign = false;
             let mut e_already_added = false;
             let mut multiplier = None;
+            let mut imaginary = false;
 
             while i < str.len() {
-                if str[i] == '.' && decimal_point_count < 1 && e_count < 1 {
+                // '.*' and './' are element-wise operators, not decimal points
+                let elementwise_op = str[i] == '.'
+                    && str
+                        .get(i + 1)
+                        .map(|it| *it == '*' || *it == '/')
+                        .unwrap_or(false);
+                if elementwise_op {
+                    break;
+                } else if str[i] == '.' && decimal_point_count < 1 && e_count < 1 {
                     decimal_point_count += 1;
                     end_index_before_last_whitespace = i + 1;
                     number_str[number_str_index] = str[i] as u8;
                     number_str_index += 1;
-                } else if str[i] == '-' && e_count == 1 {
-                    if e_neg || e_already_added {
+                } else if (str[i] == '-' || str[i] == '+') && e_count == 1 {
+                    if e_has_sign || e_already_added {
                         break;
                     }
-                    e_neg = true;
-                } else if str[i] == 'e' && e_count < 1 && !str[i - 1].is_ascii_whitespace() {
+                    e_has_sign = true;
+                    e_neg = str[i] == '-';
+                } else if (str[i] == 'e' || str[i] == 'E')
+                    && e_count < 1
+                    && !str[i - 1].is_ascii_whitespace()
+                {
                     // cannot have whitespace before 'e'
                     e_count += 1;
                 } else if str[i] == 'k'
@@ -378,6 +647,17 @@ impl TokenParser {
                     multiplier = Some(1_000_000);
                     end_index_before_last_whitespace = i + 1;
                     break;
+                } else if (str[i] == 'i' || str[i] == 'j')
+                    && digit_count > 0
+                    && !str[i - 1].is_ascii_whitespace()
+                    && str
+                        .get(i + 1)
+                        .map(|it| !it.is_alphanumeric())
+                        .unwrap_or(true)
+                {
+                    imaginary = true;
+                    end_index_before_last_whitespace = i + 1;
+                    break;
                 } else if str[i].is_ascii_digit() {
                     if e_count > 0 && !e_already_added {
                         number_str[number_str_index] = 'e' as u8;
@@ -433,7 +713,11 @@ impl TokenParser {
                         }
                     } else {
                         Some(Token {
-                            typ: TokenType::NumberLiteral(num),
+                            typ: if imaginary {
+                                TokenType::ImaginaryLiteral(num)
+                            } else {
+                                TokenType::NumberLiteral(num)
+                            },
                             ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                             has_error: false,
                         })
@@ -463,8 +747,18 @@ impl TokenParser {
         if matches!(can_be_unit, CanBeUnit::Not) || str[0].is_ascii_whitespace() {
             return None;
         }
-        let (unit, parsed_len) = unit.parse(str);
-        return if parsed_len == 0 {
+        // "in" would be an inch and "as" an attosecond
+        if TokenParser::try_extract_base_conversion(str).is_some()
+            || TokenParser::try_extract_int_type(str).is_some()
+        {
+            return None;
+        }
+        let (unit_output, parsed_len) = unit.parse(str);
+        // e.g. "15 EUR" is just text if there is no exchange rate for EUR,
+        // so it does not hide the calculation in front of it
+        let unknown_amount = matches!(can_be_unit, CanBeUnit::ApplyToPrevToken)
+            && unit.has_unknown_currency(&unit_output);
+        return if parsed_len == 0 || unknown_amount {
             None
         } else {
             // remove trailing spaces
@@ -476,12 +770,12 @@ impl TokenParser {
             match can_be_unit {
                 CanBeUnit::Not => panic!("impossible"),
                 CanBeUnit::ApplyToPrevToken => Some(Token {
-                    typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit)),
+                    typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit_output)),
                     ptr,
                     has_error: false,
                 }),
                 CanBeUnit::StandInItself => Some(Token {
-                    typ: TokenType::Unit(unit),
+                    typ: TokenType::Unit(unit_output),
                     ptr,
                     has_error: false,
                 }),
@@ -489,10 +783,410 @@ impl TokenParser {
         };
     }
 
+    /// e.g. &[3:7] or &[3]..&[7]
+    fn try_extract_line_range<'text_ptr>(
+        str: &[char],
+        vars: &Variables,
+        row_index: usize,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        if !str.starts_with(&['&', '[']) {
+            return None;
+        }
+        let digit_count = |from: usize| {
+            str.iter()
+                .skip(from)
+                .take_while(|it| it.is_ascii_digit())
+                .count()
+        };
+        let first_len = digit_count(2);
+        if first_len == 0 {
+            return None;
+        }
+        let first_id = &str[2..2 + first_len];
+        let mut i = 2 + first_len;
+        let second_id_start = if str.get(i) == Some(&':') {
+            i + 1
+        } else if str[i..].starts_with(&[']', '.', '.', '&', '[']) {
+            i + 5
+        } else {
+            return None;
+        };
+        let second_len = digit_count(second_id_start);
+        if second_len == 0 || str.get(second_id_start + second_len) != Some(&']') {
+            return None;
+        }
+        let second_id = &str[second_id_start..second_id_start + second_len];
+        i = second_id_start + second_len + 1;
+
+        let find_line_ref = |id: &[char]| {
+            vars[0..row_index].iter().position(|var| {
+                var.as_ref()
+                    .map(|var| {
+                        var.name.len() == id.len() + 3
+                            && var.name.starts_with(&['&', '['])
+                            && var.name[2..2 + id.len()] == *id
+                            && var.name[2 + id.len()] == ']'
+                    })
+                    .unwrap_or(false)
+            })
+        };
+        let first = find_line_ref(first_id)?;
+        let second = find_line_ref(second_id)?;
+        return Some(Token {
+            typ: TokenType::LineRange {
+                from: first.min(second),
+                to: first.max(second),
+            },
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
+            has_error: false,
+        });
+    }
+
+    /// e.g. 2026-10-18, 2026-10-18 14:30, today or now
+    fn try_extract_date_literal<'text_ptr>(
+        str: &[char],
+        after_operator: bool,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        // "now we have 5 apples" is just text, the keywords must be operands of an expression
+        let followed_by_operator = |from: usize| {
+            str[from..]
+                .iter()
+                .find(|it| !it.is_ascii_whitespace())
+                .map(|it| "+-*/^<>=!".contains(*it))
+                .unwrap_or(true)
+        };
+        let keyword_len = |keyword: &[char]| {
+            let full_match = starts_with_word(str, keyword)
+                && (after_operator || followed_by_operator(keyword.len()));
+            if full_match {
+                Some(keyword.len())
+            } else {
+                None
+            }
+        };
+        let (date, len) = if let Some(len) = keyword_len(&['t', 'o', 'd', 'a', 'y']) {
+            (DateTime::from_secs(current_time()?, false), len)
+        } else if let Some(len) = keyword_len(&['n', 'o', 'w']) {
+            (DateTime::from_secs(current_time()?, true), len)
+        } else {
+            TokenParser::parse_iso_date(str)?
+        };
+        Some(Token {
+            typ: TokenType::DateLiteral(date),
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(len)),
+            has_error: false,
+        })
+    }
+
+    fn parse_iso_date(str: &[char]) -> Option<(DateTime, usize)> {
+        let digits = |from: usize, len: usize| -> Option<u32> {
+            let mut value = 0;
+            for i in from..from + len {
+                value = value * 10 + str.get(i)?.to_digit(10)?;
+            }
+            Some(value)
+        };
+        let is_char = |i: usize, ch: char| str.get(i) == Some(&ch);
+        if !is_char(4, '-') || !is_char(7, '-') {
+            return None;
+        }
+        let (year, month, day) = (digits(0, 4)?, digits(5, 2)?, digits(8, 2)?);
+        let time = if (is_char(10, ' ') || is_char(10, 'T')) && is_char(13, ':') {
+            digits(11, 2).and_then(|hour| Some((hour, digits(14, 2)?)))
+        } else {
+            None
+        };
+        let (hour, min, sec, len) = match time {
+            Some((hour, min)) if is_char(16, ':') => match digits(17, 2) {
+                Some(sec) => (hour, min, sec, 19),
+                None => (hour, min, 0, 16),
+            },
+            Some((hour, min)) => (hour, min, 0, 16),
+            None => (0, 0, 0, 10),
+        };
+        if str.get(len).map(|it| it.is_alphanumeric()).unwrap_or(false) {
+            return None;
+        }
+        let date =
+            DateTime::from_ymd_hms(i64::from(year), month, day, hour, min, sec, time.is_some())?;
+        Some((date, len))
+    }
+
+    /// Clock notation (1:30:15) or more time quantities in a row (2h 30min).
+    /// Two clock times joined by '-' are a time span if the second one is later,
+    /// e.g. 9:15 - 17:40 is 8:25
+    fn try_extract_duration_literal<'text_ptr>(
+        str: &[char],
+        units: &Units,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        let (secs, len, unit_name): (i64, usize, &[char]) =
+            if let Some((start, start_len)) = TokenParser::parse_clock(str) {
+                let mut i = start_len;
+                while str
+                    .get(i)
+                    .map(|it| it.is_ascii_whitespace())
+                    .unwrap_or(false)
+                {
+                    i += 1;
+                }
+                let span = if str.get(i) == Some(&'-') {
+                    i += 1;
+                    while str
+                        .get(i)
+                        .map(|it| it.is_ascii_whitespace())
+                        .unwrap_or(false)
+                    {
+                        i += 1;
+                    }
+                    TokenParser::parse_clock(&str[i..])
+                        .filter(|(end, _)| start < *end && *end < SECONDS_PER_DAY)
+                        .map(|(end, end_len)| (end - start, i + end_len))
+                } else {
+                    None
+                };
+                let (secs, len) = span.unwrap_or((start, start_len));
+                (secs, len, &['h'])
+            } else {
+                TokenParser::parse_time_quantities(str)?
+            };
+        Some(Token {
+            typ: TokenType::DurationLiteral(Decimal::from(secs), units.parse(unit_name).0),
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(len)),
+            has_error: false,
+        })
+    }
+
+    /// e.g. 1:30 or 1:30:15
+    fn parse_clock(str: &[char]) -> Option<(i64, usize)> {
+        let two_digits = |from: usize| -> Option<i64> {
+            let tens = i64::from(str.get(from)?.to_digit(10)?);
+            let ones = i64::from(str.get(from + 1)?.to_digit(10)?);
+            Some(tens * 10 + ones).filter(|it| *it < 60)
+        };
+        let mut hours: i64 = 0;
+        let mut i = 0;
+        while let Some(digit) = str.get(i).and_then(|it| it.to_digit(10)) {
+            hours = hours.checked_mul(10)?.checked_add(i64::from(digit))?;
+            i += 1;
+        }
+        if i == 0 || str.get(i) != Some(&':') {
+            return None;
+        }
+        let mins = two_digits(i + 1)?;
+        let (secs, len) = if str.get(i + 3) == Some(&':') {
+            (two_digits(i + 4)?, i + 6)
+        } else {
+            (0, i + 3)
+        };
+        let followed_by_alnum = str
+            .get(len)
+            .map(|it| it.is_alphanumeric() || *it == ':')
+            .unwrap_or(false);
+        if followed_by_alnum {
+            return None;
+        }
+        Some((hours.checked_mul(3600)?.checked_add(mins * 60 + secs)?, len))
+    }
+
+    /// e.g. 2h 30min, 2 h 30 min or 1d 4h, at least two quantities in decreasing order.
+    /// The unit of the result is the unit of the first quantity.
+    fn parse_time_quantities(str: &[char]) -> Option<(i64, usize, &'static [char])> {
+        // the notation and the name of the unit
+        const TIME_UNITS: [(&[char], &[char], i64); 4] = [
+            (&['d'], &['d', 'a', 'y'], 24 * 60 * 60),
+            (&['h'], &['h'], 60 * 60),
+            (&['m', 'i', 'n'], &['m', 'i', 'n'], 60),
+            (&['s'], &['s'], 1),
+        ];
+        let mut secs: i64 = 0;
+        let mut len = 0;
+        let mut count = 0;
+        let mut first_unit: &'static [char] = &[];
+        let mut next_unit_index = 0;
+        let mut i = 0;
+        let skip_whitespaces = |mut i: usize| {
+            while str
+                .get(i)
+                .map(|it| it.is_ascii_whitespace())
+                .unwrap_or(false)
+            {
+                i += 1;
+            }
+            i
+        };
+        loop {
+            let mut num: i64 = 0;
+            let num_start = i;
+            while let Some(digit) = str.get(i).and_then(|it| it.to_digit(10)) {
+                num = num.checked_mul(10)?.checked_add(i64::from(digit))?;
+                i += 1;
+            }
+            if i == num_start {
+                break;
+            }
+            // like at other units, whitespace is allowed between the number and the unit
+            i = skip_whitespaces(i);
+            let unit = TIME_UNITS[next_unit_index..]
+                .iter()
+                .position(|(name, _, _)| {
+                    str[i..].starts_with(name)
+                        && !str
+                            .get(i + name.len())
+                            .map(|it| it.is_alphanumeric())
+                            .unwrap_or(false)
+                })
+                .map(|it| it + next_unit_index);
+            let unit = if let Some(unit) = unit {
+                unit
+            } else {
+                break;
+            };
+            let (name, unit_name, unit_secs) = TIME_UNITS[unit];
+            if count == 0 {
+                first_unit = unit_name;
+            }
+            secs = secs.checked_add(num.checked_mul(unit_secs)?)?;
+            count += 1;
+            i += name.len();
+            len = i;
+            next_unit_index = unit + 1;
+            i = skip_whitespaces(i);
+        }
+        if count < 2 {
+            None
+        } else {
+            Some((secs, len, first_unit))
+        }
+    }
+
+    /// e.g. "20% of 150", "30 is what % of 150", "30 is 20% of what" or "% change from 120 to 150"
+    fn try_extract_percentage_phrase<'text_ptr>(
+        str: &[char],
+        after_percentage: bool,
+        percentage_change: bool,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        // returns the length of the matched words, any whitespace is allowed between them
+        fn match_words(str: &[char], words: &[&[char]]) -> Option<usize> {
+            let mut i = 0;
+            for (word_i, word) in words.iter().enumerate() {
+                if word_i > 0 {
+                    while str
+                        .get(i)
+                        .map(|it| it.is_ascii_whitespace())
+                        .unwrap_or(false)
+                    {
+                        i += 1;
+                    }
+                }
+                if !str[i..].starts_with(word) {
+                    return None;
+                }
+                i += word.len();
+            }
+            let full_word = str.get(i).map(|it| !it.is_alphanumeric()).unwrap_or(true);
+            if full_word {
+                Some(i)
+            } else {
+                None
+            }
+        }
+        fn token<'text_ptr>(
+            typ: TokenType,
+            str: &[char],
+            len: usize,
+            allocator: &'text_ptr Bump,
+        ) -> Option<Token<'text_ptr>> {
+            Some(Token {
+                typ,
+                ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(len)),
+                has_error: false,
+            })
+        }
+        let of: &[char] = &['o', 'f'];
+        let what: &[char] = &['w', 'h', 'a', 't'];
+        let is: &[char] = &['i', 's'];
+        if let Some(len) = match_words(
+            str,
+            &[
+                &['%'],
+                &['c', 'h', 'a', 'n', 'g', 'e'],
+                &['f', 'r', 'o', 'm'],
+            ],
+        ) {
+            // the expression starts after it
+            token(TokenType::StringLiteral, str, len, allocator)
+        } else if let (true, Some(len)) = (percentage_change, match_words(str, &[&['t', 'o']])) {
+            token(
+                TokenType::Operator(OperatorTokenType::PercChange),
+                str,
+                len,
+                allocator,
+            )
+        } else if let Some(len) = match_words(str, &[is, what, &['%'], of]) {
+            token(
+                TokenType::Operator(OperatorTokenType::IsWhatPercOf),
+                str,
+                len,
+                allocator,
+            )
+        } else if let Some(len) = match_words(str, &[is]) {
+            // only if the line ends with "of what"
+            let mut end = str.len();
+            while end > 0 && str[end - 1].is_ascii_whitespace() {
+                end -= 1;
+            }
+            let ends_with_of_what =
+                (len..end).any(|i| match_words(&str[i..end], &[of, what]) == Some(end - i));
+            if ends_with_of_what {
+                token(
+                    TokenType::Operator(OperatorTokenType::IsPercOfWhat),
+                    str,
+                    len,
+                    allocator,
+                )
+            } else {
+                None
+            }
+        } else if !after_percentage {
+            None
+        } else if let Some(len) = match_words(str, &[of, what]) {
+            // the end of "30 is 20% of what"
+            token(TokenType::StringLiteral, str, len, allocator)
+        } else if let Some(len) = match_words(str, &[of]) {
+            token(
+                TokenType::Operator(OperatorTokenType::PercOf),
+                str,
+                len,
+                allocator,
+            )
+        } else if let Some(len) = match_words(str, &[&['o', 'n']]) {
+            token(
+                TokenType::Operator(OperatorTokenType::PercOn),
+                str,
+                len,
+                allocator,
+            )
+        } else if let Some(len) = match_words(str, &[&['o', 'f', 'f']]) {
+            token(
+                TokenType::Operator(OperatorTokenType::PercOff),
+                str,
+                len,
+                allocator,
+            )
+        } else {
+            None
+        }
+    }
+
     fn try_extract_variable_name<'text_ptr>(
         str: &[char],
         vars: &Variables,
         row_index: usize,
+        can_be_unit: CanBeUnit,
         allocator: &'text_ptr Bump,
     ) -> Option<Token<'text_ptr>> {
         if str.starts_with(&['s', 'u', 'm']) && str.get(3).map(|it| *it == ' ').unwrap_or(true) {
@@ -506,6 +1200,7 @@ impl TokenParser {
         }
         let mut longest_match_index = 0;
         let mut longest_match = 0;
+        let mut longest_match_is_fn = false;
         'asd: for (var_index, var) in vars[0..row_index].iter().enumerate().rev() {
             if var.is_none() {
                 continue;
@@ -516,12 +1211,12 @@ impl TokenParser {
                     continue 'asd;
                 }
             }
-            // if the next char is '(', it can't be a var name
-            if str
+            // if the next char is '(', it can't be a var name, only a user defined function
+            let followed_by_paren = str
                 .get(var.name.len())
                 .map(|it| *it == '(')
-                .unwrap_or(false)
-            {
+                .unwrap_or(false);
+            if followed_by_paren != var.user_fn.is_some() {
                 continue 'asd;
             }
             // only full match allowed e.g. if there is variable 'b', it should not match "b0" as 'b' and '0'
@@ -535,10 +1230,16 @@ impl TokenParser {
             if var.name.len() > longest_match {
                 longest_match = var.name.len();
                 longest_match_index = var_index;
+                longest_match_is_fn = followed_by_paren;
             }
         }
         return if longest_match > 0 {
-            let typ = if longest_match > 2 && str[0] == '&' && str[1] == '[' {
+            let typ = if longest_match_is_fn {
+                TokenType::Operator(OperatorTokenType::Fn {
+                    arg_count: 0, // unused in tokens, so can be fixed 0
+                    typ: FnType::UserDefined(longest_match_index),
+                })
+            } else if longest_match > 2 && str[0] == '&' && str[1] == '[' {
                 TokenType::LineReference {
                     var_index: longest_match_index,
                 }
@@ -553,17 +1254,149 @@ impl TokenParser {
                 has_error: false,
             })
         } else {
-            None
+            TokenParser::try_extract_prev_result(str, vars, row_index, allocator).or_else(|| {
+                TokenParser::try_extract_aggregate_variable(str, can_be_unit, allocator)
+            })
         };
     }
 
+    pub fn is_prev_result_name(str: &[char]) -> bool {
+        PREV_RESULT_NAMES.iter().any(|name| *name == str)
+    }
+
+    /// `prev` or `ans` refers to the nearest line above which has a result
+    fn try_extract_prev_result<'text_ptr>(
+        str: &[char],
+        vars: &Variables,
+        row_index: usize,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        let name = PREV_RESULT_NAMES.iter().find(|name| {
+            str.starts_with(name)
+                && str
+                    .get(name.len())
+                    .map(|it| !it.is_alphanumeric() && *it != '(')
+                    .unwrap_or(true)
+        })?;
+        let var_index = vars[0..row_index].iter().rposition(|var| {
+            var.as_ref()
+                .map(|var| var.value.is_ok() && var.user_fn.is_none())
+                .unwrap_or(false)
+        })?;
+        Some(Token {
+            typ: TokenType::Variable { var_index },
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(name.len())),
+            has_error: false,
+        })
+    }
+
+    /// The user defined variables shadow them, and after a number they are units (e.g. "5 min")
+    fn try_extract_aggregate_variable<'text_ptr>(
+        str: &[char],
+        can_be_unit: CanBeUnit,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        if !matches!(can_be_unit, CanBeUnit::Not) {
+            return None;
+        }
+        let (name, var_index) = AGGREGATE_VARIABLES.iter().find(|(name, _index)| {
+            str.starts_with(name)
+                && str
+                    .get(name.len())
+                    .map(|it| !it.is_alphanumeric() && *it != '(')
+                    .unwrap_or(true)
+        })?;
+        Some(Token {
+            typ: TokenType::Variable {
+                var_index: *var_index,
+            },
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(name.len())),
+            has_error: false,
+        })
+    }
+
+    /// e.g. `area(w, h) = w * h`, returns the name, the parameters and the body of the function
+    pub fn try_extract_fn_definition(line: &[char]) -> Option<(&[char], Vec<&[char]>, &[char])> {
+        let paren_open = line.iter().position(|it| *it == '(')?;
+        let paren_close = paren_open + line[paren_open..].iter().position(|it| *it == ')')?;
+        let name = trim(&line[0..paren_open]);
+        if !is_identifier(name) || FnType::value_of(name).is_some() {
+            return None;
+        }
+        let rest = trim(&line[paren_close + 1..]);
+        if rest.first() != Some(&'=') || rest.get(1) == Some(&'=') {
+            return None;
+        }
+        let body = trim(&rest[1..]);
+        if body.is_empty() {
+            return None;
+        }
+        let params_str = trim(&line[paren_open + 1..paren_close]);
+        let mut params: Vec<&[char]> = Vec::with_capacity(4);
+        if !params_str.is_empty() {
+            for param in params_str.split(|it| *it == ',').map(trim) {
+                if !is_identifier(param) || params.contains(&param) {
+                    return None;
+                }
+                params.push(param);
+            }
+        }
+        return Some((name, params, body));
+    }
+
+    /// e.g. "unit sprint = 2 week", returns the name and the value
+    pub fn try_extract_unit_definition(line: &[char]) -> Option<(&[char], &[char])> {
+        let line = trim(line);
+        if !line.starts_with(&['u', 'n', 'i', 't', ' ']) {
+            return None;
+        }
+        let eq_index = line.iter().position(|it| *it == '=')?;
+        let name = trim(&line[5..eq_index]);
+        if !is_identifier(name) || line.get(eq_index + 1) == Some(&'=') {
+            return None;
+        }
+        let body = trim(&line[eq_index + 1..]);
+        if body.is_empty() {
+            return None;
+        }
+        return Some((name, body));
+    }
+
+    /// e.g. "12.3456 @2dp" or "1234 @3sf" sets the precision of the result of the line
+    pub fn try_extract_precision_suffix(line: &[char]) -> Option<ResultPrecision> {
+        let line = trim(line);
+        let at_index = line.iter().rposition(|it| *it == '@')?;
+        if at_index > 0 && !line[at_index - 1].is_ascii_whitespace() {
+            return None;
+        }
+        let suffix = &line[at_index + 1..];
+        if suffix.len() < 3 {
+            return None;
+        }
+        let (digits, kind) = suffix.split_at(suffix.len() - 2);
+        if !digits.iter().all(|it| it.is_ascii_digit()) {
+            return None;
+        }
+        let count = digits
+            .iter()
+            .collect::<String>()
+            .parse::<usize>()
+            .ok()
+            .filter(|it| *it <= MAX_RESULT_PRECISION)?;
+        match kind {
+            ['d', 'p'] => Some(ResultPrecision::DecimalPlaces(count)),
+            ['s', 'f'] if count > 0 => Some(ResultPrecision::SignificantFigures(count)),
+            _ => None,
+        }
+    }
+
     fn try_extract_string_literal<'text_ptr>(
         str: &[char],
         allocator: &'text_ptr Bump,
     ) -> Option<Token<'text_ptr>> {
         let mut i = 0;
         for ch in str {
-            if "=%/+-*^()[]".chars().any(|it| it == *ch) || ch.is_ascii_whitespace() {
+            if "=%/+-*^()[]<>".chars().any(|it| it == *ch) || ch.is_ascii_whitespace() {
                 break;
             }
             // it means somwewhere we passed an invalid slice
@@ -599,8 +1432,135 @@ impl TokenParser {
         }
     }
 
+    /// e.g. "to base 36", returns the radix and the length of the operator
+    fn try_extract_base_conversion(str: &[char]) -> Option<(u8, usize)> {
+        if !str.starts_with(&['i', 'n', ' ']) && !str.starts_with(&['t', 'o', ' ']) {
+            return None;
+        }
+        let mut i = 3;
+        while str.get(i).map(|it| *it == ' ').unwrap_or(false) {
+            i += 1;
+        }
+        if !str[i..].starts_with(&['b', 'a', 's', 'e', ' ']) {
+            return None;
+        }
+        i += 5;
+        while str.get(i).map(|it| *it == ' ').unwrap_or(false) {
+            i += 1;
+        }
+        let digits_start = i;
+        let mut radix: u8 = 0;
+        while let Some(digit) = str.get(i).and_then(|it| it.to_digit(10)) {
+            radix = radix.checked_mul(10)?.checked_add(digit as u8)?;
+            i += 1;
+        }
+        let followed_by_alphanumeric = str.get(i).map(|it| it.is_alphanumeric()).unwrap_or(false);
+        if i == digits_start || followed_by_alphanumeric || !(2..=36).contains(&radix) {
+            return None;
+        }
+        Some((radix, i))
+    }
+
+    /// e.g. "as u8" or "as i64", returns the type and the length of the operator
+    fn try_extract_int_type(str: &[char]) -> Option<(IntType, usize)> {
+        if !str.starts_with(&['a', 's', ' ']) {
+            return None;
+        }
+        let mut i = 3;
+        while str.get(i).map(|it| *it == ' ').unwrap_or(false) {
+            i += 1;
+        }
+        let signed = match str.get(i) {
+            Some('i') => true,
+            Some('u') => false,
+            _ => return None,
+        };
+        i += 1;
+        let digits_start = i;
+        let mut bits: u8 = 0;
+        while let Some(digit) = str.get(i).and_then(|it| it.to_digit(10)) {
+            bits = bits.checked_mul(10)?.checked_add(digit as u8)?;
+            i += 1;
+        }
+        let followed_by_alphanumeric = str.get(i).map(|it| it.is_alphanumeric()).unwrap_or(false);
+        if i == digits_start || followed_by_alphanumeric || ![8, 16, 32, 64].contains(&bits) {
+            return None;
+        }
+        Some((IntType { bits, signed }, i))
+    }
+
+    /// 'and', 'or' and 'not' are operators only between boolean operands
+    /// (e.g. "2 < 3 and 3 < 4"), otherwise they are just words of the text
+    fn try_extract_logical_operator<'text_ptr>(
+        str: &[char],
+        after_comparison: bool,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        let (typ, len) = if after_comparison && starts_with_word(str, &['a', 'n', 'd']) {
+            (OperatorTokenType::And, 3)
+        } else if after_comparison && starts_with_word(str, &['o', 'r']) {
+            (OperatorTokenType::Or, 2)
+        } else if starts_with_word(str, &['n', 'o', 't']) {
+            (OperatorTokenType::Not, 3)
+        } else {
+            return None;
+        };
+        if !TokenParser::operand_has_comparison(&str[len..]) {
+            return None;
+        }
+        Some(Token {
+            typ: TokenType::Operator(typ),
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(len)),
+            has_error: false,
+        })
+    }
+
+    /// whether the operand starting at 'str' (until a ',', ';' or an unmatched closing paren)
+    /// contains a comparison
+    fn operand_has_comparison(str: &[char]) -> bool {
+        let mut depth = 0;
+        for (i, ch) in str.iter().enumerate() {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' | ']' => {
+                    if depth == 0 {
+                        return false;
+                    }
+                    depth -= 1;
+                }
+                ',' | ';' if depth == 0 => return false,
+                '=' | '!' if str.get(i + 1) == Some(&'=') => return true,
+                '<' | '>' => {
+                    // "<<" and ">>" are shifts
+                    let shift = str.get(i + 1) == Some(ch) || (i > 0 && str[i - 1] == *ch);
+                    if !shift {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// 'in' is always a conversion, 'to' only if a unit follows it (e.g. "from 1 to 5")
+    fn starts_with_unit_converter(str: &[char], units: &Units) -> bool {
+        if str.starts_with(&['i', 'n', ' ']) {
+            return true;
+        } else if !str.starts_with(&['t', 'o', ' ']) {
+            return false;
+        }
+        str.iter()
+            .skip(2)
+            .position(|it| !it.is_ascii_whitespace())
+            .map(|unit_start| units.parse(&str[2 + unit_start..]).1 > 0)
+            .unwrap_or(false)
+    }
+
     fn try_extract_operator<'text_ptr>(
         str: &[char],
+        units: &Units,
+        after_operand: bool,
         allocator: &'text_ptr Bump,
     ) -> Option<Token<'text_ptr>> {
         fn op<'text_ptr>(
@@ -617,7 +1577,14 @@ impl TokenParser {
             });
         }
         match str[0] {
-            '=' => op(OperatorTokenType::Assign, str, 1, allocator),
+            '=' => {
+                // in "a == 12", where 'a' is not a variable, these are just two '='
+                if after_operand && str.get(1) == Some(&'=') {
+                    op(OperatorTokenType::Equal, str, 2, allocator)
+                } else {
+                    op(OperatorTokenType::Assign, str, 1, allocator)
+                }
+            }
             '+' => op(OperatorTokenType::Add, str, 1, allocator),
             '-' => op(OperatorTokenType::Sub, str, 1, allocator),
             '*' => op(OperatorTokenType::Mult, str, 1, allocator),
@@ -631,8 +1598,16 @@ impl TokenParser {
             ',' => op(OperatorTokenType::Comma, str, 1, allocator),
             ';' => op(OperatorTokenType::Semicolon, str, 1, allocator),
             _ => {
-                if str.starts_with(&['i', 'n', ' ']) {
+                if let Some((radix, len)) = TokenParser::try_extract_base_conversion(str) {
+                    op(OperatorTokenType::ToBase(radix), str, len, allocator)
+                } else if let Some((int_type, len)) = TokenParser::try_extract_int_type(str) {
+                    op(OperatorTokenType::AsInt(int_type), str, len, allocator)
+                } else if TokenParser::starts_with_unit_converter(str, units) {
                     op(OperatorTokenType::UnitConverter, str, 2, allocator)
+                } else if str.starts_with(&['.', '*']) {
+                    op(OperatorTokenType::ElementwiseMult, str, 2, allocator)
+                } else if str.starts_with(&['.', '/']) {
+                    op(OperatorTokenType::ElementwiseDiv, str, 2, allocator)
                 } else if str.starts_with(&['A', 'N', 'D'])
                     && str.get(3).map(|it| !it.is_alphabetic()).unwrap_or(true)
                 {
@@ -653,6 +1628,16 @@ impl TokenParser {
                     op(OperatorTokenType::ShiftLeft, str, 2, allocator)
                 } else if str.starts_with(&['>', '>']) {
                     op(OperatorTokenType::ShiftRight, str, 2, allocator)
+                } else if str.starts_with(&['<', '=']) {
+                    op(OperatorTokenType::LessOrEqual, str, 2, allocator)
+                } else if str.starts_with(&['>', '=']) {
+                    op(OperatorTokenType::GreaterOrEqual, str, 2, allocator)
+                } else if str.starts_with(&['!', '=']) {
+                    op(OperatorTokenType::NotEqual, str, 2, allocator)
+                } else if str[0] == '<' {
+                    op(OperatorTokenType::Less, str, 1, allocator)
+                } else if str[0] == '>' {
+                    op(OperatorTokenType::Greater, str, 1, allocator)
                 } else {
                     None
                 }
@@ -661,6 +1646,34 @@ impl TokenParser {
     }
 }
 
+fn trim(str: &[char]) -> &[char] {
+    let start = str
+        .iter()
+        .position(|it| !it.is_ascii_whitespace())
+        .unwrap_or(str.len());
+    let end = str
+        .iter()
+        .rposition(|it| !it.is_ascii_whitespace())
+        .map(|it| it + 1)
+        .unwrap_or(start);
+    &str[start..end.max(start)]
+}
+
+fn starts_with_word(str: &[char], word: &[char]) -> bool {
+    str.starts_with(word)
+        && str
+            .get(word.len())
+            .map(|it| !it.is_alphanumeric() && *it != '_')
+            .unwrap_or(true)
+}
+
+fn is_identifier(str: &[char]) -> bool {
+    str.first()
+        .map(|it| it.is_alphabetic() || *it == '_')
+        .unwrap_or(false)
+        && str.iter().all(|it| it.is_alphanumeric() || *it == '_')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -668,7 +1681,7 @@ mod tests {
     use crate::helper::create_vars;
     use crate::shunting_yard::tests::*;
     use crate::units::units::Units;
-    use crate::{Variable, MAX_LINE_COUNT};
+    use crate::{Variable, VARIABLE_COUNT};
 
     #[test]
     fn test_number_parsing() {
@@ -714,9 +1727,11 @@ mod tests {
         test_parse("0b0101", 5);
         test_parse("0b0101 1010", 90);
         test_parse("0b0101 101     1", 91);
+        test_parse("0b0101_1010", 90);
 
         test_parse("0x1", 1);
         test_parse("0xAB Cd e    f", 11_259_375);
+        test_parse("0xFFFF_FFFF", 4_294_967_295);
 
         test_parse("1", 1);
         test_parse("123456", 123456);
@@ -731,14 +1746,51 @@ mod tests {
         test_parse_f("123.456.3", "123.456");
     }
 
+    #[test]
+    fn test_fn_definition_parsing() {
+        fn test_def(text: &str, expected: Option<(&str, &[&str], &str)>) {
+            let line = text.chars().collect::<Vec<_>>();
+            let actual =
+                TokenParser::try_extract_fn_definition(&line).map(|(name, params, body)| {
+                    (
+                        name.iter().collect::<String>(),
+                        params
+                            .iter()
+                            .map(|it| it.iter().collect::<String>())
+                            .collect::<Vec<_>>(),
+                        body.iter().collect::<String>(),
+                    )
+                });
+            let expected = expected.map(|(name, params, body)| {
+                (
+                    name.to_owned(),
+                    params.iter().map(|it| (*it).to_owned()).collect::<Vec<_>>(),
+                    body.to_owned(),
+                )
+            });
+            assert_eq!(actual, expected, "{}", text);
+        }
+        test_def("area(w, h) = w * h", Some(("area", &["w", "h"], "w * h")));
+        test_def("  f( x )=x^2 ", Some(("f", &["x"], "x^2")));
+        test_def("pi2() = 2 * pi()", Some(("pi2", &[], "2 * pi()")));
+        test_def("f(x) =", None);
+        test_def("f(x) == 2", None);
+        test_def("f(x, x) = x", None);
+        test_def("f(2) = 2", None);
+        test_def("sin(x) = x", None);
+        test_def("my fn(x) = x", None);
+        test_def("a = f(x)", None);
+    }
+
     fn test_vars(var_names: &[&'static [char]], text: &str, expected_tokens: &[Token]) {
-        let var_names: Vec<Option<Variable>> = (0..MAX_LINE_COUNT + 1)
+        let var_names: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
             .into_iter()
             .map(|index| {
                 if let Some(var_name) = var_names.get(index) {
                     Some(Variable {
                         name: Box::from(*var_name),
                         value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                        user_fn: None,
                     })
                 } else {
                     None
@@ -1090,6 +2142,32 @@ mod tests {
             ],
         );
 
+        test(
+            "45min to h",
+            &[
+                num(45),
+                apply_to_prev_token_unit("min"),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("h"),
+            ],
+        );
+
+        // 'to' is not a conversion if no unit follows it
+        test(
+            "from 1 to 5",
+            &[
+                str("from"),
+                str(" "),
+                num(1),
+                str(" "),
+                str("to"),
+                str(" "),
+                num(5),
+            ],
+        );
+
         test(
             "10(km/h)^2 * 45min in m",
             &[
@@ -1334,6 +2412,9 @@ mod tests {
         test("3e", &[num(3), str("e")]);
         test("33e", &[num(33), str("e")]);
         test("3e3", &[num(3000)]);
+        test("1.2e-9", &[numf(1.2e-9f64)]);
+        test("1.5E3", &[num(1500)]);
+        test("1e+5", &[num(100000)]);
         test(
             "3e--3",
             &[
@@ -3,6 +3,7 @@ use std::str::FromStr;
 
 use rust_decimal::prelude::*;
 
+use crate::units::currency::CURRENCIES;
 use crate::units::units::{UnitInstance, Units};
 use crate::units::{Prefix, Unit, UnitPrefixes};
 use std::cell::RefCell;
@@ -52,6 +53,20 @@ pub const ALL_UNIT_COUNT: usize = 25;
 pub const EMPTY_UNIT_DIMENSIONS: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT] =
     [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
 
+/// in the order of the exponents in the dimension arrays
+pub const BASE_UNIT_DIMENSION_NAMES: [&str; BASE_UNIT_DIMENSION_COUNT] = [
+    "mass",
+    "length",
+    "time",
+    "current",
+    "temperature",
+    "luminous intensity",
+    "amount of substance",
+    "angle",
+    "bit",
+    "money",
+];
+
 pub(crate) const BASE_UNIT_DIMENSIONS: [[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT];
     ALL_UNIT_COUNT] = [
     [1, 0, 0, 0, 0, 0, 0, 0, 0, 0], // Mass
@@ -303,7 +318,7 @@ fn create_prefixes() -> UnitPrefixes {
 pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
     let prefixes = create_prefixes();
     let pi: Decimal = Decimal::from_str("3.14159265358979323846264338327950288").unwrap();
-    let mut map = HashMap::<&str, Unit>::with_capacity(168);
+    let mut map = HashMap::<&str, Unit>::with_capacity(196);
 
     map.insert(
         "meter",
@@ -2021,6 +2036,20 @@ pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
         },
     );
 
+    for &(code, name) in CURRENCIES.iter() {
+        // their value is set by the exchange rates
+        map.insert(
+            code,
+            Unit {
+                name,
+                base: BASE_UNIT_DIMENSIONS[UnitType::Money as usize],
+                prefix_groups: (None, None),
+                value: Decimal::from_i64(1).unwrap(),
+                offset: Decimal::from_i64(0).unwrap(),
+            },
+        );
+    }
+
     let map = map
         .into_iter()
         .map(|(key, value)| (key, RefCell::new(value)))
@@ -2226,6 +2255,12 @@ pub fn get_base_unit_for(
             prefix: RefCell::clone(&units.no_prefix),
             power: 1,
         })
+    } else if dimensions == &BASE_UNIT_DIMENSIONS[UnitType::ElectricPotential as usize] {
+        Some(UnitInstance {
+            unit: RefCell::clone(&units.units["V"]),
+            prefix: RefCell::clone(&units.no_prefix),
+            power: 1,
+        })
     } else if dimensions == &BASE_UNIT_DIMENSIONS[UnitType::ElectricResistance as usize] {
         Some(UnitInstance {
             unit: RefCell::clone(&units.units["ohm"]),
@@ -0,0 +1,216 @@
+use crate::units::units::{UnitOutput, Units};
+use crate::units::Unit;
+use rust_decimal::prelude::*;
+use std::cell::RefCell;
+use std::str::FromStr;
+
+/// Their value is always 1, the other currencies are converted through them
+const BASE_CURRENCIES: [&str; 2] = ["$", "USD"];
+
+pub const CURRENCIES: [(&str, &[char]); 28] = [
+    ("USD", &['U', 'S', 'D']),
+    ("EUR", &['E', 'U', 'R']),
+    ("GBP", &['G', 'B', 'P']),
+    ("JPY", &['J', 'P', 'Y']),
+    ("CHF", &['C', 'H', 'F']),
+    ("CAD", &['C', 'A', 'D']),
+    ("AUD", &['A', 'U', 'D']),
+    ("NZD", &['N', 'Z', 'D']),
+    ("CNY", &['C', 'N', 'Y']),
+    ("HKD", &['H', 'K', 'D']),
+    ("SGD", &['S', 'G', 'D']),
+    ("INR", &['I', 'N', 'R']),
+    ("KRW", &['K', 'R', 'W']),
+    ("THB", &['T', 'H', 'B']),
+    ("BRL", &['B', 'R', 'L']),
+    ("MXN", &['M', 'X', 'N']),
+    ("ZAR", &['Z', 'A', 'R']),
+    ("ILS", &['I', 'L', 'S']),
+    ("TRY", &['T', 'R', 'Y']),
+    ("RUB", &['R', 'U', 'B']),
+    ("SEK", &['S', 'E', 'K']),
+    ("NOK", &['N', 'O', 'K']),
+    ("DKK", &['D', 'K', 'K']),
+    ("ISK", &['I', 'S', 'K']),
+    ("PLN", &['P', 'L', 'N']),
+    ("CZK", &['C', 'Z', 'K']),
+    ("HUF", &['H', 'U', 'F']),
+    ("RON", &['R', 'O', 'N']),
+];
+
+/// 1 `from` = `rate` `to`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExchangeRate {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub rate: Decimal,
+}
+
+fn currency_code(name: &str) -> Option<&'static str> {
+    BASE_CURRENCIES
+        .iter()
+        .chain(CURRENCIES.iter().map(|(code, _)| code))
+        .find(|it| **it == name)
+        .copied()
+}
+
+/// e.g. "1 EUR = 1.08 USD"
+pub fn parse_exchange_rate(line: &[char]) -> Option<ExchangeRate> {
+    fn parse_amount_and_currency(str: &[char]) -> Option<(Decimal, &'static str)> {
+        let str = str.iter().collect::<String>();
+        let mut parts = str.split_whitespace();
+        let amount = Decimal::from_str(parts.next()?)
+            .ok()
+            .filter(|it| *it > Decimal::zero())?;
+        let code = currency_code(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some((amount, code))
+    }
+
+    let eq_index = line.iter().position(|it| *it == '=')?;
+    let (from_amount, from) = parse_amount_and_currency(&line[0..eq_index])?;
+    let (to_amount, to) = parse_amount_and_currency(&line[eq_index + 1..])?;
+    if from == to {
+        return None;
+    }
+    Some(ExchangeRate {
+        from,
+        to,
+        rate: to_amount.checked_div(&from_amount)?,
+    })
+}
+
+impl Units {
+    pub fn exchange_rate(&self, row: usize) -> Option<ExchangeRate> {
+        self.exchange_rates.borrow().get(row).cloned().flatten()
+    }
+
+    pub fn set_exchange_rate(&self, row: usize, rate: Option<ExchangeRate>) {
+        let mut rates = self.exchange_rates.borrow_mut();
+        if rates.len() <= row {
+            rates.resize(row + 1, None);
+        }
+        rates[row] = rate;
+    }
+
+    pub fn clear_exchange_rates(&self) {
+        self.exchange_rates.borrow_mut().clear();
+    }
+
+    /// Loads rates which are available for every note, e.g. from a local file in headless use.
+    /// Every line has the same format as in the notes (e.g. "1 EUR = 1.08 USD"),
+    /// empty lines and lines starting with '#' are skipped.
+    /// Returns false if there was an invalid line, the valid ones are loaded anyway.
+    pub fn load_exchange_rates(&self, content: &str) -> bool {
+        let mut valid = true;
+        for line in content.lines().map(|it| it.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rate) = parse_exchange_rate(&line.chars().collect::<Vec<_>>()) {
+                self.default_exchange_rates.borrow_mut().push(rate);
+            } else {
+                valid = false;
+            }
+        }
+        valid
+    }
+
+    /// The value of the currencies in USD based on the loaded rates and the ones declared
+    /// above the visible row.
+    /// A rate is ignored if neither of its currencies can be converted to USD yet.
+    fn currency_values(&self) -> Vec<(&'static str, Decimal)> {
+        let mut values: Vec<(&'static str, Decimal)> = BASE_CURRENCIES
+            .iter()
+            .map(|code| (*code, Decimal::one()))
+            .collect();
+        fn set(values: &mut Vec<(&'static str, Decimal)>, code: &'static str, value: Decimal) {
+            if let Some(entry) = values.iter_mut().find(|(it, _)| *it == code) {
+                entry.1 = value;
+            } else {
+                values.push((code, value));
+            }
+        }
+        fn value_of(values: &[(&'static str, Decimal)], code: &str) -> Option<Decimal> {
+            values
+                .iter()
+                .find(|(it, _)| *it == code)
+                .map(|(_, value)| *value)
+        }
+
+        let default_rates = self.default_exchange_rates.borrow();
+        let rates = self.exchange_rates.borrow();
+        let visible_rows = self.visible_declaration_rows.get().min(rates.len());
+        for rate in default_rates
+            .iter()
+            .chain(rates[0..visible_rows].iter().filter_map(|it| it.as_ref()))
+        {
+            match (value_of(&values, rate.from), value_of(&values, rate.to)) {
+                (_, Some(to_value)) if !BASE_CURRENCIES.contains(&rate.from) => {
+                    if let Some(value) = to_value.checked_mul(&rate.rate) {
+                        set(&mut values, rate.from, value);
+                    }
+                }
+                (Some(from_value), _) if !BASE_CURRENCIES.contains(&rate.to) => {
+                    if let Some(value) = from_value.checked_div(&rate.rate) {
+                        set(&mut values, rate.to, value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        values
+    }
+
+    /// None if there is no known exchange rate for the currency
+    pub(super) fn currency_value(&self, code: &str) -> Option<Decimal> {
+        self.currency_values()
+            .into_iter()
+            .find(|(it, _)| *it == code)
+            .map(|(_, value)| value)
+    }
+
+    /// The value of a currency unit is its exchange rate at the visible row
+    pub(super) fn with_exchange_rate(&self, unit: RefCell<Unit>) -> RefCell<Unit> {
+        let code = currency_code(&unit.borrow().name.iter().collect::<String>());
+        if let Some(value) = code.and_then(|code| self.currency_value(code)) {
+            unit.borrow_mut().value = value;
+        }
+        unit
+    }
+
+    /// e.g. "EUR" if no rate was declared for it
+    pub fn has_unknown_currency(&self, unit: &UnitOutput) -> bool {
+        unit.units.iter().any(|unit_instance| {
+            let unit = unit_instance.unit.borrow();
+            currency_code(&unit.name.iter().collect::<String>())
+                .map(|code| self.currency_value(code).is_none())
+                .unwrap_or(false)
+        })
+    }
+
+    /// False if the operands contain different currencies without a known exchange rate,
+    /// or their amount was calculated with an outdated rate.
+    pub fn can_exchange(&self, a: &UnitOutput, b: &UnitOutput) -> bool {
+        let mut first_code = None;
+        let mut mixed = false;
+        let mut all_known = true;
+        for unit_instance in a.units.iter().chain(b.units.iter()) {
+            let unit = unit_instance.unit.borrow();
+            let code = if let Some(code) = currency_code(&unit.name.iter().collect::<String>()) {
+                code
+            } else {
+                continue;
+            };
+            match self.currency_value(code) {
+                Some(value) if value != unit.value => return false,
+                Some(_) => {}
+                None => all_known = false,
+            }
+            mixed |= *first_code.get_or_insert(code) != code;
+        }
+        !mixed || all_known
+    }
+}
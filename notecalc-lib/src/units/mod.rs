@@ -6,6 +6,7 @@ use smallvec::alloc::fmt::Formatter;
 use std::cell::RefCell;
 
 pub mod consts;
+pub mod currency;
 pub mod units;
 
 #[derive(Eq, PartialEq, Clone)]
@@ -1,13 +1,15 @@
-use crate::calc::pow;
+use crate::calc::{pow, CalcError};
 use crate::units::consts::{
     get_base_unit_for, init_aliases, init_units, UnitDimensionExponent, BASE_UNIT_DIMENSIONS,
     BASE_UNIT_DIMENSION_COUNT,
 };
+use crate::units::currency::ExchangeRate;
 use crate::units::{Prefix, Unit, UnitPrefixes};
+use rust_decimal::prelude::Zero;
 use rust_decimal::Decimal;
 use smallvec::alloc::fmt::{Debug, Display, Formatter};
 use smallvec::SmallVec;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Write;
@@ -28,6 +30,15 @@ fn parse_unit(str: &[char]) -> Option<&[char]> {
     Some(&str[0..i])
 }
 
+/// The units declared in the note can have '_' in their name, e.g. "story_point"
+fn parse_user_unit(str: &[char]) -> &[char] {
+    let len = str
+        .iter()
+        .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+        .count();
+    &str[0..len]
+}
+
 fn skip_whitespaces(str: &[char]) -> &[char] {
     let mut i = 0;
     for ch in str {
@@ -44,6 +55,16 @@ pub struct Units {
     pub units: HashMap<&'static str, RefCell<Unit>>,
     pub aliases: HashMap<&'static str, &'static str>,
     pub no_prefix: RefCell<Prefix>,
+    // exchange rates declared in the note, indexed by their row
+    pub(super) exchange_rates: RefCell<Vec<Option<ExchangeRate>>>,
+    pub(super) default_exchange_rates: RefCell<Vec<ExchangeRate>>,
+    // units declared in the note (e.g. "unit sprint = 2 week"), indexed by their row
+    user_units: RefCell<Vec<Option<RefCell<Unit>>>>,
+    user_unit_names: RefCell<Vec<&'static [char]>>,
+    // only the declarations above this row are used while parsing and evaluating it
+    pub(super) visible_declaration_rows: Cell<usize>,
+    // results are shown in their composite unit (e.g. kg m / s^2) instead of the named one (N)
+    keep_raw_units: Cell<bool>,
 }
 
 impl Units {
@@ -54,6 +75,12 @@ impl Units {
             units,
             prefixes,
             aliases: init_aliases(),
+            exchange_rates: RefCell::new(Vec::with_capacity(8)),
+            default_exchange_rates: RefCell::new(Vec::with_capacity(8)),
+            user_units: RefCell::new(Vec::with_capacity(8)),
+            user_unit_names: RefCell::new(Vec::with_capacity(8)),
+            visible_declaration_rows: Cell::new(usize::MAX),
+            keep_raw_units: Cell::new(false),
         }
     }
 
@@ -111,7 +138,14 @@ impl Units {
             };
 
             // Verify the unit exists and get the prefix (if any)
-            let res = if let Some(res) = self.find_unit(u_str) {
+            let user_unit_str = parse_user_unit(c);
+            let res = if let Some(unit) = self
+                .find_user_unit(user_unit_str)
+                .filter(|_| user_unit_str.len() > u_str.len())
+            {
+                c = skip(c, user_unit_str.len());
+                (unit, RefCell::clone(&self.no_prefix))
+            } else if let Some(res) = self.find_unit(u_str) {
                 c = skip(c, u_str.len());
                 res
             } else {
@@ -204,6 +238,91 @@ impl Units {
         return (output, last_valid_cursor_pos);
     }
 
+    /// Only the declarations above `row` can be used while parsing and evaluating it
+    pub fn apply_declarations_above(&self, row: usize) {
+        self.visible_declaration_rows.set(row);
+    }
+
+    pub fn clear_declarations(&self) {
+        self.user_units.borrow_mut().clear();
+        self.visible_declaration_rows.set(usize::MAX);
+        self.clear_exchange_rates();
+    }
+
+    /// The unit can't have prefixes, and it can't hide a builtin unit.
+    /// `value` and `dimensions` are the value of the unit in base units, e.g. 1209600 s for "2 week"
+    pub fn set_user_unit(
+        &self,
+        row: usize,
+        name: &[char],
+        value: Decimal,
+        dimensions: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+    ) -> Result<(), CalcError> {
+        if self.find_builtin_unit(name).is_some() {
+            self.remove_user_unit(row);
+            return Err(CalcError::BuiltinUnitName);
+        } else if value.is_zero() {
+            self.remove_user_unit(row);
+            return Err(CalcError::ZeroUnitValue);
+        }
+        let name = self.intern_user_unit_name(name);
+        let mut user_units = self.user_units.borrow_mut();
+        if user_units.len() <= row {
+            user_units.resize(row + 1, None);
+        }
+        user_units[row] = Some(RefCell::new(Unit {
+            name,
+            base: dimensions,
+            prefix_groups: (None, None),
+            value,
+            offset: Decimal::zero(),
+        }));
+        Ok(())
+    }
+
+    pub fn remove_user_unit(&self, row: usize) {
+        if let Some(unit) = self.user_units.borrow_mut().get_mut(row) {
+            *unit = None;
+        }
+    }
+
+    pub fn user_unit(&self, row: usize) -> Option<RefCell<Unit>> {
+        self.user_units.borrow().get(row).cloned().flatten()
+    }
+
+    /// The names of the units declared above `row`, e.g. for autocompletion
+    pub fn user_unit_names(&self, row: usize) -> Vec<&'static [char]> {
+        self.user_units
+            .borrow()
+            .iter()
+            .take(row)
+            .filter_map(|it| it.as_ref().map(|it| it.borrow().name))
+            .collect()
+    }
+
+    /// The units must have static names, so every distinct name is leaked once
+    fn intern_user_unit_name(&self, name: &[char]) -> &'static [char] {
+        let mut names = self.user_unit_names.borrow_mut();
+        if let Some(interned) = names.iter().find(|it| **it == name) {
+            return *interned;
+        }
+        let interned: &'static [char] = Box::leak(Box::from(name));
+        names.push(interned);
+        interned
+    }
+
+    fn find_user_unit(&self, str: &[char]) -> Option<RefCell<Unit>> {
+        let user_units = self.user_units.borrow();
+        let visible_rows = self.visible_declaration_rows.get().min(user_units.len());
+        // the last declaration wins
+        user_units[0..visible_rows]
+            .iter()
+            .rev()
+            .filter_map(|it| it.as_ref())
+            .find(|it| it.borrow().name == str)
+            .map(|it| RefCell::clone(it))
+    }
+
     fn calc_parsed_len(text: &[char], current: &[char]) -> usize {
         let mut parsed_len = unsafe { current.as_ptr().offset_from(text.as_ptr()) } as usize;
         // remove spaces
@@ -214,6 +333,15 @@ impl Units {
     }
 
     fn find_unit(&self, str: &[char]) -> Option<(RefCell<Unit>, RefCell<Prefix>)> {
+        self.find_builtin_unit(str)
+            .map(|(unit, prefix)| (self.with_exchange_rate(unit), prefix))
+            .or_else(|| {
+                self.find_user_unit(str)
+                    .map(|unit| (unit, RefCell::clone(&self.no_prefix)))
+            })
+    }
+
+    fn find_builtin_unit(&self, str: &[char]) -> Option<(RefCell<Unit>, RefCell<Prefix>)> {
         if str.is_empty() {
             return None;
         }
@@ -271,6 +399,10 @@ impl Units {
         return None;
     }
 
+    pub fn set_keep_raw_units(&self, keep: bool) {
+        self.keep_raw_units.set(keep);
+    }
+
     pub fn simplify(&self, unit: &UnitOutput) -> Option<UnitOutput> {
         if let Some(base_unit) = get_base_unit_for(self, &unit.dimensions) {
             let dimensions = base_unit.unit.borrow().base;
@@ -465,6 +597,9 @@ impl UnitOutput {
     }
 
     pub fn simplify(&self, units: &Units) -> Option<UnitOutput> {
+        if units.keep_raw_units.get() {
+            return None;
+        }
         if let Some(base_unit) = units.simplify(self) {
             // e.g. don't convert from km to m, but convert from kg*m/s^2 to N
             // base_unit.units.len() is always 1
@@ -619,6 +754,30 @@ impl UnitOutput {
         };
     }
 
+    /// e.g. m with exponent -9 is nm, only a single unit without prefix can get one
+    pub fn with_si_prefix(&self, exponent: i32) -> Option<UnitOutput> {
+        if exponent == 0
+            || self.units.len() != 1
+            || self.units[0].power != 1
+            || !self.units[0].prefix.borrow().name.is_empty()
+        {
+            return None;
+        }
+        let value = Decimal::from_scientific(&format!("1e{}", exponent)).ok()?;
+        let unit = self.units[0].unit.borrow();
+        let prefixes = unit.prefix_groups.0.as_ref()?.borrow();
+        let prefix = prefixes
+            .iter()
+            .find(|it| {
+                let it = it.borrow();
+                it.scientific && it.value == value
+            })?
+            .clone();
+        let mut result = self.clone();
+        result.units[0].prefix = prefix;
+        Some(result)
+    }
+
     pub fn pow(&self, p: i64) -> Option<UnitOutput> {
         let mut result = self.clone();
         let p = i8::try_from(p).ok()?;
@@ -632,6 +791,37 @@ impl UnitOutput {
         return Some(result);
     }
 
+    /// None if the dimensions are not divisible by n, e.g. sqrt(9 m)
+    pub fn root(&self, n: i64, units: &Units) -> Option<UnitOutput> {
+        let n = i8::try_from(n).ok().filter(|it| *it > 0)?;
+        if self.dimensions.iter().any(|it| it % n != 0) {
+            return None;
+        }
+        let mut result = self.clone();
+        for dim in &mut result.dimensions {
+            *dim /= n;
+        }
+        if result.units.iter().all(|it| it.power % n == 0) {
+            for unit in &mut result.units {
+                unit.power /= n;
+            }
+        } else {
+            // e.g. sqrt(4 m * 1 m), the value is stored in base units anyway
+            result.units.clear();
+            for i in 0..BASE_UNIT_DIMENSION_COUNT {
+                if result.dimensions[i] != 0 {
+                    let base_unit = get_base_unit_for(units, &BASE_UNIT_DIMENSIONS[i])?;
+                    result.units.push(UnitInstance {
+                        unit: base_unit.unit,
+                        prefix: base_unit.prefix,
+                        power: result.dimensions[i],
+                    });
+                }
+            }
+        }
+        return Some(result);
+    }
+
     pub fn is_derived(&self) -> bool {
         self.units.len() > 1 || (self.units.len() == 1 && self.units[0].power > 1)
     }
@@ -1144,4 +1334,22 @@ mod tests {
         assert_eq!(unit1.units[0].unit.borrow().name, &['$']);
         assert_eq!(unit1.units[0].power, 1);
     }
+
+    #[test]
+    fn test_loading_exchange_rates() {
+        let units = Units::new();
+        assert!(units.load_exchange_rates("# rates\n1 EUR = 1.25 USD\n\n1 USD = 20 CZK\n"));
+        assert!(!units.load_exchange_rates("1 EUR = 1.25"));
+
+        let eur = parse("EUR", &units);
+        let czk = parse("CZK", &units);
+        let huf = parse("HUF", &units);
+        assert!(units.can_exchange(&eur, &czk));
+        assert!(!units.can_exchange(&eur, &huf));
+        let norm = eur.normalize(&Decimal::from_i64(10).unwrap()).unwrap();
+        assert_eq!(
+            czk.from_base_to_this_unit(&norm).unwrap(),
+            Decimal::from_i64(250).unwrap()
+        );
+    }
 }
@@ -223,3 +223,14 @@ fn test_that_no_autocompletion_for_multiple_results() {
     test.input(EditorInputEvent::Tab, InputModifiers::none());
     assert_eq!("apple = 12$\nananas = 7$\na   ", test.get_editor_content());
 }
+
+#[test]
+fn test_user_defined_unit_autocompletion() {
+    let test = create_app2(35);
+    test.paste("unit story_point = 4 hour\n3 sto");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(
+        "unit story_point = 4 hour\n3 story_point",
+        test.get_editor_content()
+    );
+}
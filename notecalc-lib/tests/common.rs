@@ -57,7 +57,7 @@ impl BorrowCheckerFighter {
     }
 
     pub fn mut_vars<'a>(&self) -> &'a mut [Option<Variable>] {
-        unsafe { &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; MAX_LINE_COUNT + 1]))[..] }
+        unsafe { &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; VARIABLE_COUNT]))[..] }
     }
 
     pub fn allocator<'a>(&self) -> &'a Bump {